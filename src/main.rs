@@ -9,8 +9,8 @@ extern crate glm;
 extern crate image;
 extern crate geometry_kernel;
 extern crate glib;
+extern crate gdk_pixbuf;
 
-use std::io::Cursor;
 use std::ptr;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -48,53 +48,2749 @@ struct VertexModel {
     position: [f32; 3],
     tex_coords: [f32; 2],
     normal: [f32; 3],
+    color: [f32; 4],
+    tangent: [f32; 4],
 }
-implement_vertex!(VertexModel, position, tex_coords, normal);
+implement_vertex!(VertexModel, position, tex_coords, normal, color, tangent);
 
-fn make_model(path : &str) -> Vec<VertexModel>{
+fn triangle_normal(points: &[[f32; 3]; 3]) -> [f32; 3] {
+    let u = [points[1][0] - points[0][0], points[1][1] - points[0][1], points[1][2] - points[0][2]];
+    let v = [points[2][0] - points[0][0], points[2][1] - points[0][1], points[2][2] - points[0][2]];
+    let n = [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]];
+    let length = (n[0]*n[0] + n[1]*n[1] + n[2]*n[2]).sqrt();
+    if length > 0.0 {
+        [n[0]/length, n[1]/length, n[2]/length]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
 
-    let mut maximum = 1.0f32;
+// a file whose size matches the triangle count of a binary header is binary whatever its first
+// bytes say, since some exporters write `solid` into the binary header too
+fn is_ascii_stl(bytes: &[u8]) -> bool {
+    if bytes.len() >= 84 {
+        let count = bytes[80] as u64 | (bytes[81] as u64) << 8 |
+            (bytes[82] as u64) << 16 | (bytes[83] as u64) << 24;
+        if 84 + 50 * count == bytes.len() as u64 {
+            return false;
+        }
+    }
+    let head = &bytes[..bytes.len().min(512)];
+    !head.contains(&0) && String::from_utf8_lossy(head).trim_start().starts_with("solid")
+}
+
+fn parse_stl_vector(line_number: usize, words: &[&str]) -> Result<[f32; 3], String> {
+    if words.len() != 3 {
+        return Err(format!("line {}: expected 3 coordinates, found {}", line_number, words.len()));
+    }
+    let mut vector = [0.0f32; 3];
+    for i in 0..3 {
+        vector[i] = words[i].parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| {
+            format!("line {}: `{}` is not a finite number", line_number, words[i])
+        })?;
+    }
+    Ok(vector)
+}
+
+fn expect_stl_line<'a>(lines: &'a [(usize, Vec<&'a str>)], i: usize, keyword: &str,
+                       facet_line: usize) -> Result<&'a (usize, Vec<&'a str>), String> {
+    match lines.get(i) {
+        Some(line) if line.1[0] == keyword => Ok(line),
+        Some(line) => Err(format!("line {}: expected `{}`, found `{}`", line.0, keyword, line.1[0])),
+        None => Err(format!("line {}: facet is not terminated", facet_line)),
+    }
+}
+
+fn read_ascii_stl(text: &str) -> Result<Vec<VertexModel>, String> {
+    let mut model = vec![];
+    let lines: Vec<(usize, Vec<&str>)> = text.lines().enumerate()
+        .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+        .filter(|line| !line.1.is_empty())
+        .collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let (facet_line, ref words) = lines[i];
+        match words[0] {
+            "solid" | "endsolid" => {
+                i += 1;
+            },
+            "facet" => {
+                if words.len() < 2 || words[1] != "normal" {
+                    return Err(format!("line {}: expected `facet normal`", facet_line));
+                }
+                let normal = parse_stl_vector(facet_line, &words[2..])?;
+                let outer = expect_stl_line(&lines, i + 1, "outer", facet_line)?;
+                if outer.1.len() != 2 || outer.1[1] != "loop" {
+                    return Err(format!("line {}: expected `outer loop`", outer.0));
+                }
+                let mut points = [[0.0f32; 3]; 3];
+                for k in 0..3 {
+                    let vertex = expect_stl_line(&lines, i + 2 + k, "vertex", facet_line)?;
+                    points[k] = parse_stl_vector(vertex.0, &vertex.1[1..])?;
+                }
+                expect_stl_line(&lines, i + 5, "endloop", facet_line)?;
+                expect_stl_line(&lines, i + 6, "endfacet", facet_line)?;
+                let n = if normal == [0.0, 0.0, 0.0] { triangle_normal(&points) } else { normal };
+                for p in &points {
+                    model.push(VertexModel {
+                        position: *p,
+                        tex_coords: [p[0], p[1]],
+                        normal: n,
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        tangent: [0.0; 4],
+                    });
+                }
+                i += 7;
+            },
+            other => {
+                return Err(format!("line {}: unexpected `{}`", facet_line, other));
+            }
+        }
+    }
+    Ok(model)
+}
+
+#[derive(Clone, Default)]
+struct MeshStats {
+    triangle_count: usize,
+    vertex_count: usize,
+    area: f64,
+    volume: f64,
+    size: [f64; 3],
+    centroid: [f64; 3],
+    is_exact: bool,
+}
+
+fn add_exact<T: std::ops::Add<Output = T>>(sum: Option<T>, value: T) -> Option<T> {
+    Some(match sum {
+        Some(sum) => sum + value,
+        None => value,
+    })
+}
+
+// the kernel's numbers are exact rationals; the denominators of sums of products of f32 coordinates are
+// powers of two well inside the f64 range, so both halves convert without overflow
+fn exact_to_f64(value: &geometry_kernel::primitives::number::Number) -> f64 {
+    f64::from(&value.get_num()) / f64::from(&value.get_den())
+}
+
+fn read_kernel_mesh(path: &str, bytes: &[u8]) -> Result<Mesh, String> {
+    Mesh::read_stl(&mut std::io::Cursor::new(bytes)).map_err(|_| {
+        format!("{}: not a valid binary STL file", path)
+    })
+}
+
+fn read_binary_stl(path: &str, bytes: &[u8]) -> Result<(Vec<VertexModel>, MeshStats), String> {
+    read_kernel_mesh(path, bytes).map(|model_mesh| mesh_vertices(&model_mesh))
+}
+
+fn mesh_vertices(model_mesh: &Mesh) -> (Vec<VertexModel>, MeshStats) {
     let mut model = vec![];
-    let mut model_file = File::open(path).unwrap();
-    let model_mesh = Mesh::read_stl(&mut model_file).unwrap();
     let triangle_indices = model_mesh.get_it_iterator();
     use geometry_kernel::primitives::number::NumberTrait;
+    let mut area = 0.0f64;
+    let mut volume6 = None;
+    let mut moments = [None, None, None];
     for i in triangle_indices {
         let triangle = model_mesh.get_triangle(i);
         let n = triangle.get_normal();
         let points = triangle.get_points();
+        {
+            let (a, b, c) = (&points[0], &points[1], &points[2]);
+            let det = a.x.clone() * (b.y.clone() * c.z.clone() - b.z.clone() * c.y.clone()) +
+                a.y.clone() * (b.z.clone() * c.x.clone() - b.x.clone() * c.z.clone()) +
+                a.z.clone() * (b.x.clone() * c.y.clone() - b.y.clone() * c.x.clone());
+            moments[0] = add_exact(moments[0].take(), det.clone() * (a.x.clone() + b.x.clone() + c.x.clone()));
+            moments[1] = add_exact(moments[1].take(), det.clone() * (a.y.clone() + b.y.clone() + c.y.clone()));
+            moments[2] = add_exact(moments[2].take(), det.clone() * (a.z.clone() + b.z.clone() + c.z.clone()));
+            volume6 = add_exact(volume6, det);
+            let u = [b.x.clone() - a.x.clone(), b.y.clone() - a.y.clone(), b.z.clone() - a.z.clone()];
+            let v = [c.x.clone() - a.x.clone(), c.y.clone() - a.y.clone(), c.z.clone() - a.z.clone()];
+            let nx = u[1].clone() * v[2].clone() - u[2].clone() * v[1].clone();
+            let ny = u[2].clone() * v[0].clone() - u[0].clone() * v[2].clone();
+            let nz = u[0].clone() * v[1].clone() - u[1].clone() * v[0].clone();
+            let length2 = nx.clone() * nx + ny.clone() * ny + nz.clone() * nz;
+            area += exact_to_f64(&length2).sqrt() / 2.0;
+        }
         for p in points {
             let px = p.x.convert_to_f32();
             let py = p.y.convert_to_f32();
             let pz = p.z.convert_to_f32();
-            let step_max = px.abs().max(py.max(pz.abs()));
-            maximum = maximum.max(step_max);
             let nx = n.clone().x.convert_to_f32();
             let ny = n.clone().y.convert_to_f32();
             let nz = n.clone().z.convert_to_f32();
             model.push(VertexModel {
                 position: [px, py, pz],
                 tex_coords: [px, py], //tex_coords[index],
-                normal: [nx, ny, nz]
+                normal: [nx, ny, nz],
+                color: [1.0, 1.0, 1.0, 1.0],
+                tangent: [0.0; 4],
+            });
+        }
+    }
+    let volume6 = volume6.as_ref().map(exact_to_f64).unwrap_or(0.0);
+    let mut centroid = [0.0f64; 3];
+    if volume6 != 0.0 {
+        for i in 0..3 {
+            centroid[i] = moments[i].as_ref().map(exact_to_f64).unwrap_or(0.0) / (4.0 * volume6);
+        }
+    }
+    let stats = MeshStats {
+        area: area, volume: volume6 / 6.0, centroid: centroid, is_exact: true, .. Default::default()
+    };
+    (model, stats)
+}
+
+fn float_stats(vertices: &[VertexModel], indices: &[u32]) -> MeshStats {
+    let mut area = 0.0f64;
+    let mut volume6 = 0.0f64;
+    let mut moments = [0.0f64; 3];
+    for triangle in indices.chunks(3) {
+        let point = |k: usize| {
+            let p = vertices[triangle[k] as usize].position;
+            [p[0] as f64, p[1] as f64, p[2] as f64]
+        };
+        let (a, b, c) = (point(0), point(1), point(2));
+        let det = a[0] * (b[1]*c[2] - b[2]*c[1]) + a[1] * (b[2]*c[0] - b[0]*c[2]) + a[2] * (b[0]*c[1] - b[1]*c[0]);
+        for i in 0..3 {
+            moments[i] += det * (a[i] + b[i] + c[i]);
+        }
+        volume6 += det;
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]];
+        area += (n[0]*n[0] + n[1]*n[1] + n[2]*n[2]).sqrt() / 2.0;
+    }
+    let mut centroid = [0.0f64; 3];
+    if volume6 != 0.0 {
+        for i in 0..3 {
+            centroid[i] = moments[i] / (4.0 * volume6);
+        }
+    }
+    MeshStats {
+        area: area, volume: volume6 / 6.0, centroid: centroid, .. Default::default()
+    }
+}
+
+// children of an inner node (count == 0) sit at its index + 1 and at `right`
+#[derive(Clone, Default)]
+struct BvhNode {
+    min: [f32; 3],
+    max: [f32; 3],
+    start: usize,
+    count: usize,
+    right: usize,
+}
+
+#[derive(Clone, Default)]
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<u32>,
+}
+
+impl Bvh {
+    fn build(vertices: &[VertexModel], indices: &[u32]) -> Bvh {
+        let mut bvh = Bvh { nodes: vec![], triangles: (0..(indices.len() / 3) as u32).collect() };
+        let centroids: Vec<[f32; 3]> = indices.chunks(3).map(|triangle| {
+            let (a, b, c) = (vertices[triangle[0] as usize].position, vertices[triangle[1] as usize].position,
+                             vertices[triangle[2] as usize].position);
+            [(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0, (a[2] + b[2] + c[2]) / 3.0]
+        }).collect();
+        let count = bvh.triangles.len();
+        if count > 0 {
+            bvh.build_node(vertices, indices, &centroids, 0, count);
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, vertices: &[VertexModel], indices: &[u32], centroids: &[[f32; 3]],
+                  start: usize, end: usize) -> usize {
+        let mut min = [std::f32::MAX; 3];
+        let mut max = [std::f32::MIN; 3];
+        let mut centroid_min = [std::f32::MAX; 3];
+        let mut centroid_max = [std::f32::MIN; 3];
+        for &triangle in &self.triangles[start..end] {
+            let t = triangle as usize;
+            for k in 0..3 {
+                let p = vertices[indices[3*t + k] as usize].position;
+                for i in 0..3 {
+                    min[i] = min[i].min(p[i]);
+                    max[i] = max[i].max(p[i]);
+                }
+            }
+            for i in 0..3 {
+                centroid_min[i] = centroid_min[i].min(centroids[t][i]);
+                centroid_max[i] = centroid_max[i].max(centroids[t][i]);
+            }
+        }
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode { min: min, max: max, start: start, count: end - start, right: 0 });
+        let extent = [centroid_max[0] - centroid_min[0], centroid_max[1] - centroid_min[1],
+                      centroid_max[2] - centroid_min[2]];
+        let axis = if extent[0] >= extent[1] && extent[0] >= extent[2] { 0 } else if extent[1] >= extent[2] { 1 } else { 2 };
+        if end - start <= 4 || extent[axis] <= 0.0 {
+            return index;
+        }
+        self.triangles[start..end].sort_by(|a, b| {
+            centroids[*a as usize][axis].partial_cmp(&centroids[*b as usize][axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let middle = (start + end) / 2;
+        self.nodes[index].count = 0;
+        self.build_node(vertices, indices, centroids, start, middle);
+        let right = self.build_node(vertices, indices, centroids, middle, end);
+        self.nodes[index].right = right;
+        index
+    }
+
+    // nearest triangle hit by the ray and the ray parameter of the hit
+    fn intersect(&self, vertices: &[VertexModel], indices: &[u32], origin: [f32; 3], dir: [f32; 3])
+                 -> Option<(usize, f32)> {
+        let inverse = [1.0 / dir[0], 1.0 / dir[1], 1.0 / dir[2]];
+        let mut best: Option<(usize, f32)> = None;
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let (mut near, mut far) = (0.0f32, best.map(|best| best.1).unwrap_or(std::f32::MAX));
+            for i in 0..3 {
+                let t0 = (node.min[i] - origin[i]) * inverse[i];
+                let t1 = (node.max[i] - origin[i]) * inverse[i];
+                near = near.max(t0.min(t1));
+                far = far.min(t0.max(t1));
+            }
+            if near > far {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.right);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in &self.triangles[node.start..node.start + node.count] {
+                let t = triangle as usize;
+                let a = vertices[indices[3*t] as usize].position;
+                let b = vertices[indices[3*t + 1] as usize].position;
+                let c = vertices[indices[3*t + 2] as usize].position;
+                let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+                let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+                let p = cross3(dir, e2);
+                let det = dot3(e1, p);
+                if det.abs() < 1e-12 {
+                    continue;
+                }
+                let s = [origin[0] - a[0], origin[1] - a[1], origin[2] - a[2]];
+                let u = dot3(s, p) / det;
+                let q = cross3(s, e1);
+                let v = dot3(dir, q) / det;
+                let distance = dot3(e2, q) / det;
+                if u < 0.0 || v < 0.0 || u + v > 1.0 || distance < 0.0 || distance.is_nan() {
+                    continue;
+                }
+                if best.map(|best| distance < best.1).unwrap_or(true) {
+                    best = Some((t, distance));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[derive(Clone)]
+struct ModelData {
+    vertices: Vec<VertexModel>,
+    indices: Vec<u32>,
+    has_tex_coords: bool,
+    has_normals: bool,
+    has_colors: bool,
+    maximum: f32,
+    bbox_min: [f32; 3],
+    bbox_max: [f32; 3],
+    centroid: [f32; 3],
+    stats: MeshStats,
+    report: MeshReport,
+    bvh: Bvh,
+}
+
+impl ModelData {
+    fn new(vertices: Vec<VertexModel>, has_tex_coords: bool, has_normals: bool, has_colors: bool) -> ModelData {
+        ModelData {
+            vertices: vertices, indices: vec![],
+            has_tex_coords: has_tex_coords, has_normals: has_normals, has_colors: has_colors,
+            maximum: 1.0, bbox_min: [0.0; 3], bbox_max: [0.0; 3], centroid: [0.0; 3],
+            stats: MeshStats::default(), report: MeshReport::default(), bvh: Bvh::default(),
+        }
+    }
+
+    fn update_bounds(&mut self) {
+        let mut bbox_min = [std::f32::MAX; 3];
+        let mut bbox_max = [std::f32::MIN; 3];
+        for vertex in &self.vertices {
+            for i in 0..3 {
+                bbox_min[i] = bbox_min[i].min(vertex.position[i]);
+                bbox_max[i] = bbox_max[i].max(vertex.position[i]);
+            }
+        }
+        if self.vertices.is_empty() {
+            bbox_min = [0.0; 3];
+            bbox_max = [0.0; 3];
+        }
+        let mut weighted = [0.0f64; 3];
+        let mut total_area = 0.0f64;
+        for triangle in self.indices.chunks(3) {
+            let a = self.vertices[triangle[0] as usize].position;
+            let b = self.vertices[triangle[1] as usize].position;
+            let c = self.vertices[triangle[2] as usize].position;
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let n = [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]];
+            let area = ((n[0]*n[0] + n[1]*n[1] + n[2]*n[2]) as f64).sqrt() / 2.0;
+            for i in 0..3 {
+                weighted[i] += area * (a[i] + b[i] + c[i]) as f64 / 3.0;
+            }
+            total_area += area;
+        }
+        self.bbox_min = bbox_min;
+        self.bbox_max = bbox_max;
+        self.centroid = if total_area > 0.0 {
+            [(weighted[0] / total_area) as f32, (weighted[1] / total_area) as f32,
+             (weighted[2] / total_area) as f32]
+        } else {
+            self.center()
+        };
+    }
+
+    fn center(&self) -> [f32; 3] {
+        [(self.bbox_min[0] + self.bbox_max[0]) / 2.0, (self.bbox_min[1] + self.bbox_max[1]) / 2.0,
+         (self.bbox_min[2] + self.bbox_max[2]) / 2.0]
+    }
+
+    fn size(&self) -> [f32; 3] {
+        [self.bbox_max[0] - self.bbox_min[0], self.bbox_max[1] - self.bbox_min[1],
+         self.bbox_max[2] - self.bbox_min[2]]
+    }
+
+    fn radius(&self) -> f32 {
+        let size = self.size();
+        (size[0]*size[0] + size[1]*size[1] + size[2]*size[2]).sqrt().max(1e-6) / 2.0
+    }
+}
+
+fn parse_obj_floats(line_number: usize, words: &[&str], min: usize, max: usize) -> Result<[f32; 3], String> {
+    if words.len() < min || words.len() > max {
+        return Err(format!("line {}: expected {} to {} numbers, found {}", line_number, min, max, words.len()));
+    }
+    let mut vector = [0.0f32; 3];
+    for i in 0..words.len().min(3) {
+        vector[i] = words[i].parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| {
+            format!("line {}: `{}` is not a finite number", line_number, words[i])
+        })?;
+    }
+    Ok(vector)
+}
+
+fn parse_obj_index(line_number: usize, word: Option<&str>, count: usize) -> Result<Option<usize>, String> {
+    let word = match word {
+        Some(word) if !word.is_empty() => word,
+        _ => return Ok(None),
+    };
+    let index = word.parse::<i64>().map_err(|_| {
+        format!("line {}: `{}` is not an index", line_number, word)
+    })?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("line {}: index {} is out of range", line_number, index));
+    }
+    Ok(Some(resolved as usize))
+}
+
+fn read_obj(text: &str) -> Result<ModelData, String> {
+    let mut positions = vec![];
+    let mut tex_coords = vec![];
+    let mut normals = vec![];
+    let mut triangles = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match words[0] {
+            "v" => positions.push(parse_obj_floats(line_number, &words[1..], 3, 7)?),
+            "vt" => {
+                let t = parse_obj_floats(line_number, &words[1..], 1, 3)?;
+                tex_coords.push([t[0], t[1]]);
+            },
+            "vn" => normals.push(parse_obj_floats(line_number, &words[1..], 3, 3)?),
+            "f" => {
+                if words.len() < 4 {
+                    return Err(format!("line {}: a face needs at least 3 vertices", line_number));
+                }
+                let mut corners = vec![];
+                for word in &words[1..] {
+                    let mut parts = word.split('/');
+                    let v = parse_obj_index(line_number, parts.next(), positions.len())?;
+                    let vt = parse_obj_index(line_number, parts.next(), tex_coords.len())?;
+                    let vn = parse_obj_index(line_number, parts.next(), normals.len())?;
+                    match v {
+                        Some(v) => corners.push((v, vt, vn)),
+                        None => return Err(format!("line {}: `{}` has no vertex index", line_number, word)),
+                    }
+                }
+                for k in 1..corners.len() - 1 {
+                    triangles.push([corners[0], corners[k], corners[k + 1]]);
+                }
+            },
+            _ => (),
+        }
+    }
+    let has_tex_coords = triangles.iter().any(|t| t.iter().any(|c| c.1.is_some()));
+    let has_normals = triangles.iter().any(|t| t.iter().any(|c| c.2.is_some()));
+    let mut vertices = vec![];
+    for triangle in &triangles {
+        let points = [positions[triangle[0].0], positions[triangle[1].0], positions[triangle[2].0]];
+        let facet_normal = triangle_normal(&points);
+        for &(v, vt, vn) in triangle {
+            let p = positions[v];
+            vertices.push(VertexModel {
+                position: p,
+                tex_coords: match vt {
+                    Some(vt) => tex_coords[vt],
+                    None if has_tex_coords => [0.0, 0.0],
+                    None => [p[0], p[1]],
+                },
+                normal: match vn {
+                    Some(vn) => normals[vn],
+                    None => facet_normal,
+                },
+                color: [1.0, 1.0, 1.0, 1.0],
+                tangent: [0.0; 4],
             });
         }
     }
-    let mut normalize_model = vec![];
-    for vertex in &model {
-        normalize_model.push(VertexModel {
-            position: [vertex.position[0]/maximum,
-                vertex.position[1]/maximum, vertex.position[2]/maximum],
-            tex_coords: [(vertex.tex_coords[0]/maximum + 1.0)/2.0,
-                (vertex.tex_coords[1]/maximum + 1.0)/2.0],
-            normal: vertex.normal
-        })
+    Ok(ModelData::new(vertices, has_tex_coords, has_normals, false))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PlyType {
+    Int8, UInt8, Int16, UInt16, Int32, UInt32, Float32, Float64,
+}
+
+impl PlyType {
+    fn from_name(name: &str) -> Option<PlyType> {
+        match name {
+            "char" | "int8" => Some(PlyType::Int8),
+            "uchar" | "uint8" => Some(PlyType::UInt8),
+            "short" | "int16" => Some(PlyType::Int16),
+            "ushort" | "uint16" => Some(PlyType::UInt16),
+            "int" | "int32" => Some(PlyType::Int32),
+            "uint" | "uint32" => Some(PlyType::UInt32),
+            "float" | "float32" => Some(PlyType::Float32),
+            "double" | "float64" => Some(PlyType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match *self {
+            PlyType::Int8 | PlyType::UInt8 => 1,
+            PlyType::Int16 | PlyType::UInt16 => 2,
+            PlyType::Int32 | PlyType::UInt32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+
+    fn color_scale(&self) -> f32 {
+        match *self {
+            PlyType::UInt8 => 255.0,
+            PlyType::UInt16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    data_type: PlyType,
+    list_count: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyReader<'a> {
+    format: PlyFormat,
+    bytes: &'a [u8],
+    offset: usize,
+    line_number: usize,
+    words: Vec<String>,
+    word: usize,
+}
+
+impl<'a> PlyReader<'a> {
+    fn begin_row(&mut self, element: &str) -> Result<(), String> {
+        if self.format != PlyFormat::Ascii {
+            return Ok(());
+        }
+        loop {
+            if self.offset >= self.bytes.len() {
+                return Err(format!("unexpected end of file in element `{}`", element));
+            }
+            let end = self.bytes[self.offset..].iter().position(|&b| b == b'\n')
+                .map(|end| self.offset + end + 1).unwrap_or(self.bytes.len());
+            let line = String::from_utf8_lossy(&self.bytes[self.offset..end]).into_owned();
+            self.offset = end;
+            self.line_number += 1;
+            self.words = line.split_whitespace().map(|word| word.to_string()).collect();
+            self.word = 0;
+            if !self.words.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read(&mut self, data_type: PlyType) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            let line_number = self.line_number;
+            let value = self.words.get(self.word).ok_or_else(|| {
+                format!("line {}: too few values", line_number)
+            })?.parse::<f64>().map_err(|_| {
+                format!("line {}: `{}` is not a number", line_number, self.words[self.word])
+            })?;
+            self.word += 1;
+            return Ok(value);
+        }
+        let size = data_type.size();
+        if self.offset + size > self.bytes.len() {
+            return Err("unexpected end of binary data".to_string());
+        }
+        let mut bits = 0u64;
+        for i in 0..size {
+            let byte = if self.format == PlyFormat::BinaryLittleEndian {
+                self.bytes[self.offset + size - 1 - i]
+            } else {
+                self.bytes[self.offset + i]
+            };
+            bits = bits << 8 | byte as u64;
+        }
+        self.offset += size;
+        Ok(match data_type {
+            PlyType::Int8 => bits as u8 as i8 as f64,
+            PlyType::UInt8 => bits as u8 as f64,
+            PlyType::Int16 => bits as u16 as i16 as f64,
+            PlyType::UInt16 => bits as u16 as f64,
+            PlyType::Int32 => bits as u32 as i32 as f64,
+            PlyType::UInt32 => bits as u32 as f64,
+            PlyType::Float32 => f32::from_bits(bits as u32) as f64,
+            PlyType::Float64 => f64::from_bits(bits),
+        })
+    }
+}
+
+fn read_ply(bytes: &[u8]) -> Result<ModelData, String> {
+    let header_end = bytes.windows(10).position(|window| window == b"end_header")
+        .ok_or_else(|| "missing `end_header`".to_string())?;
+    let data_start = bytes[header_end..].iter().position(|&b| b == b'\n')
+        .map(|end| header_end + end + 1).unwrap_or(bytes.len());
+    let header = String::from_utf8_lossy(&bytes[..header_end]).into_owned();
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    let mut header_lines = 0;
+    for (i, line) in header.lines().enumerate() {
+        let line_number = i + 1;
+        header_lines = line_number;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if i == 0 {
+            if words != ["ply"] {
+                return Err("not a PLY file".to_string());
+            }
+            continue;
+        }
+        if words.is_empty() {
+            continue;
+        }
+        match words[0] {
+            "format" => format = match words.get(1) {
+                Some(&"ascii") => Some(PlyFormat::Ascii),
+                Some(&"binary_little_endian") => Some(PlyFormat::BinaryLittleEndian),
+                Some(&"binary_big_endian") => Some(PlyFormat::BinaryBigEndian),
+                _ => return Err(format!("line {}: unknown format", line_number)),
+            },
+            "element" => {
+                if words.len() != 3 {
+                    return Err(format!("line {}: expected `element <name> <count>`", line_number));
+                }
+                let count = words[2].parse::<usize>().map_err(|_| {
+                    format!("line {}: `{}` is not a count", line_number, words[2])
+                })?;
+                elements.push(PlyElement { name: words[1].to_string(), count: count, properties: vec![] });
+            },
+            "property" => {
+                let property = if words.len() == 5 && words[1] == "list" {
+                    match (PlyType::from_name(words[2]), PlyType::from_name(words[3])) {
+                        (Some(count), Some(data_type)) => PlyProperty {
+                            name: words[4].to_string(), data_type: data_type, list_count: Some(count),
+                        },
+                        _ => return Err(format!("line {}: unknown list type", line_number)),
+                    }
+                } else if words.len() == 3 {
+                    match PlyType::from_name(words[1]) {
+                        Some(data_type) => PlyProperty {
+                            name: words[2].to_string(), data_type: data_type, list_count: None,
+                        },
+                        None => return Err(format!("line {}: unknown type `{}`", line_number, words[1])),
+                    }
+                } else {
+                    return Err(format!("line {}: malformed property", line_number));
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(format!("line {}: property outside of an element", line_number)),
+                }
+            },
+            "comment" | "obj_info" => (),
+            other => return Err(format!("line {}: unexpected `{}`", line_number, other)),
+        }
+    }
+    let format = format.ok_or_else(|| "missing `format` line".to_string())?;
+    let mut reader = PlyReader {
+        format: format, bytes: bytes, offset: data_start,
+        line_number: header_lines + 1, words: vec![], word: 0,
+    };
+    let mut vertex_rows: Vec<Vec<f64>> = vec![];
+    let mut vertex_names: Vec<String> = vec![];
+    let mut vertex_types: Vec<PlyType> = vec![];
+    let mut faces: Vec<Vec<usize>> = vec![];
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex {
+            vertex_names = element.properties.iter().map(|p| p.name.clone()).collect();
+            vertex_types = element.properties.iter().map(|p| p.data_type).collect();
+        }
+        for _ in 0..element.count {
+            reader.begin_row(&element.name)?;
+            let mut row = vec![];
+            for property in &element.properties {
+                match property.list_count {
+                    Some(count_type) => {
+                        let count = reader.read(count_type)? as usize;
+                        let mut list = vec![];
+                        for _ in 0..count {
+                            list.push(reader.read(property.data_type)? as usize);
+                        }
+                        if is_face && (property.name == "vertex_indices" || property.name == "vertex_index") {
+                            faces.push(list);
+                        }
+                        row.push(0.0);
+                    },
+                    None => row.push(reader.read(property.data_type)?),
+                }
+            }
+            if is_vertex {
+                vertex_rows.push(row);
+            }
+        }
+    }
+    let find = |names: &[&str]| vertex_names.iter().position(|name| names.contains(&name.as_str()));
+    let position = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+        (Some(x), Some(y), Some(z)) => [x, y, z],
+        _ => return Err("vertex element has no x/y/z properties".to_string()),
+    };
+    let normal = match (find(&["nx"]), find(&["ny"]), find(&["nz"])) {
+        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+        _ => None,
+    };
+    let color = match (find(&["red", "diffuse_red"]), find(&["green", "diffuse_green"]),
+                       find(&["blue", "diffuse_blue"])) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+    let alpha = find(&["alpha"]);
+    let tex = match (find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"])) {
+        (Some(u), Some(v)) => Some([u, v]),
+        _ => None,
+    };
+    let mut vertices = vec![];
+    for (i, face) in faces.iter().enumerate() {
+        if face.len() < 3 {
+            return Err(format!("face {} has less than 3 vertices", i));
+        }
+        if let Some(&index) = face.iter().find(|&&index| index >= vertex_rows.len()) {
+            return Err(format!("face {}: vertex index {} is out of range", i, index));
+        }
+        for k in 1..face.len() - 1 {
+            let corners = [face[0], face[k], face[k + 1]];
+            let get = |row: usize, columns: [usize; 3]| [
+                vertex_rows[row][columns[0]] as f32,
+                vertex_rows[row][columns[1]] as f32,
+                vertex_rows[row][columns[2]] as f32,
+            ];
+            let points = [get(corners[0], position), get(corners[1], position), get(corners[2], position)];
+            let facet_normal = triangle_normal(&points);
+            for (&corner, p) in corners.iter().zip(points.iter()) {
+                vertices.push(VertexModel {
+                    position: *p,
+                    tex_coords: match tex {
+                        Some(tex) => [vertex_rows[corner][tex[0]] as f32, vertex_rows[corner][tex[1]] as f32],
+                        None => [p[0], p[1]],
+                    },
+                    normal: match normal {
+                        Some(normal) => get(corner, normal),
+                        None => facet_normal,
+                    },
+                    color: match color {
+                        Some(color) => {
+                            let c = get(corner, color);
+                            let scale = vertex_types[color[0]].color_scale();
+                            let a = match alpha {
+                                Some(alpha) => vertex_rows[corner][alpha] as f32 / vertex_types[alpha].color_scale(),
+                                None => 1.0,
+                            };
+                            [c[0] / scale, c[1] / scale, c[2] / scale, a]
+                        },
+                        None => [1.0, 1.0, 1.0, 1.0],
+                    },
+                    tangent: [0.0; 4],
+                });
+            }
+        }
+    }
+    Ok(ModelData::new(vertices, tex.is_some(), normal.is_some(), color.is_some()))
+}
+
+const WELD_TOLERANCE: f32 = 1e-5;
+
+// merges vertices within `tolerance` of each other, looking through the 27 grid cells around each
+// vertex; attributes that came from the file must match for two vertices to merge, and normals are
+// left to shade_model unless the file supplied them
+fn weld_vertices(vertices: &[VertexModel], tolerance: f32, keep_tex_coords: bool, keep_normals: bool,
+                 keep_colors: bool) -> (Vec<VertexModel>, Vec<u32>) {
+    use std::collections::HashMap;
+    let mut welded: Vec<VertexModel> = vec![];
+    let mut indices = Vec::with_capacity(vertices.len());
+    let mut grid: HashMap<(i64, i64, i64), Vec<u32>> = HashMap::new();
+    let cell = |p: [f32; 3]| ((p[0] / tolerance).floor() as i64, (p[1] / tolerance).floor() as i64,
+                              (p[2] / tolerance).floor() as i64);
+    for vertex in vertices {
+        let p = vertex.position;
+        let key = cell(p);
+        let mut found = None;
+        'search: for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    let candidates = match grid.get(&(key.0 + dx, key.1 + dy, key.2 + dz)) {
+                        Some(candidates) => candidates,
+                        None => continue,
+                    };
+                    for &candidate in candidates {
+                        let other = &welded[candidate as usize];
+                        let q = other.position;
+                        let d = [p[0] - q[0], p[1] - q[1], p[2] - q[2]];
+                        if d[0]*d[0] + d[1]*d[1] + d[2]*d[2] <= tolerance * tolerance &&
+                            (!keep_tex_coords || other.tex_coords == vertex.tex_coords) &&
+                            (!keep_normals || other.normal == vertex.normal) &&
+                            (!keep_colors || other.color == vertex.color) {
+                            found = Some(candidate);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        let index = match found {
+            Some(index) => index,
+            None => {
+                welded.push(VertexModel {
+                    normal: if keep_normals { vertex.normal } else { [0.0, 0.0, 0.0] }, .. *vertex
+                });
+                let index = (welded.len() - 1) as u32;
+                grid.entry(key).or_insert_with(Vec::new).push(index);
+                index
+            },
+        };
+        indices.push(index);
+    }
+    (welded, indices)
+}
+
+#[derive(Clone, Default)]
+struct MeshReport {
+    boundary_edges: usize,
+    non_manifold_edges: usize,
+    inconsistent_edges: usize,
+    degenerate_triangles: usize,
+    duplicate_triangles: usize,
+    flipped_normals: usize,
+    problem_triangles: Vec<[f32; 3]>,
+    problem_edges: Vec<[f32; 3]>,
+}
+
+impl MeshReport {
+    fn is_printable(&self) -> bool {
+        self.boundary_edges == 0 && self.non_manifold_edges == 0 && self.inconsistent_edges == 0 &&
+            self.degenerate_triangles == 0 && self.duplicate_triangles == 0 && self.flipped_normals == 0
+    }
+
+    fn rows(&self) -> [(&'static str, usize); 6] {
+        [("boundary edges", self.boundary_edges),
+         ("non-manifold edges", self.non_manifold_edges),
+         ("inconsistent winding", self.inconsistent_edges),
+         ("degenerate triangles", self.degenerate_triangles),
+         ("duplicate triangles", self.duplicate_triangles),
+         ("flipped normals", self.flipped_normals)]
+    }
+
+    fn summary(&self) -> String {
+        if self.is_printable() {
+            "watertight and manifold".to_string()
+        } else if self.boundary_edges == 0 && self.non_manifold_edges == 0 {
+            "closed, but with defects".to_string()
+        } else {
+            "not watertight".to_string()
+        }
+    }
+}
+
+// `vertices` is a triangle soup straight from the loader, three corners per facet
+fn validate_mesh(vertices: &[VertexModel], tolerance: f32, has_facet_normals: bool) -> MeshReport {
+    use std::collections::{HashMap, HashSet};
+    let mut report = MeshReport::default();
+    // corners are matched exactly as the weld matches them, by position alone
+    let (welded, corners) = weld_vertices(vertices, tolerance, false, false, false);
+    let corners: Vec<usize> = corners.into_iter().map(|corner| corner as usize).collect();
+    let mut is_problem = vec![false; vertices.len() / 3];
+    let mut facets = HashSet::new();
+    let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::new();
+    for t in 0..vertices.len() / 3 {
+        let c = [corners[3*t], corners[3*t + 1], corners[3*t + 2]];
+        let p = [vertices[3*t].position, vertices[3*t + 1].position, vertices[3*t + 2].position];
+        let u = [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]];
+        let v = [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]];
+        let n = [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]];
+        let length = (n[0]*n[0] + n[1]*n[1] + n[2]*n[2]).sqrt();
+        if c[0] == c[1] || c[1] == c[2] || c[2] == c[0] || length <= tolerance * tolerance {
+            report.degenerate_triangles += 1;
+            is_problem[t] = true;
+            continue;
+        }
+        let mut sorted = c;
+        sorted.sort();
+        if !facets.insert(sorted) {
+            report.duplicate_triangles += 1;
+            is_problem[t] = true;
+        }
+        if has_facet_normals {
+            let f = vertices[3*t].normal;
+            if f[0]*n[0] + f[1]*n[1] + f[2]*n[2] < 0.0 {
+                report.flipped_normals += 1;
+                is_problem[t] = true;
+            }
+        }
+        for i in 0..3 {
+            let (a, b) = (c[i], c[(i + 1) % 3]);
+            edges.entry((a.min(b), a.max(b))).or_insert_with(Vec::new).push((t, a < b));
+        }
+    }
+    let positions: Vec<_> = welded.iter().map(|vertex| vertex.position).collect();
+    for (&(a, b), uses) in &edges {
+        let is_bad = match uses.len() {
+            1 => { report.boundary_edges += 1; true },
+            2 if uses[0].1 == uses[1].1 => {
+                report.inconsistent_edges += 1;
+                for &(t, _) in uses {
+                    is_problem[t] = true;
+                }
+                true
+            },
+            2 => false,
+            _ => { report.non_manifold_edges += 1; true },
+        };
+        if is_bad {
+            report.problem_edges.push(positions[a]);
+            report.problem_edges.push(positions[b]);
+        }
+    }
+    for (t, &is_problem) in is_problem.iter().enumerate() {
+        if is_problem {
+            for k in 0..3 {
+                report.problem_triangles.push(vertices[3*t + k].position);
+            }
+        }
+    }
+    report
+}
+
+fn make_model(path : &str) -> Result<ModelData, String> {
+    use std::io::Read;
+    let mut bytes = vec![];
+    File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    read_model(path, &bytes)
+}
+
+fn read_model(path: &str, bytes: &[u8]) -> Result<ModelData, String> {
+    let extension = std::path::Path::new(path).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let mut exact_stats = None;
+    let data = match extension.as_ref().map(|extension| extension.as_str()) {
+        Some("obj") => read_obj(&String::from_utf8_lossy(bytes)).map_err(|e| format!("{}: {}", path, e))?,
+        Some("ply") => read_ply(bytes).map_err(|e| format!("{}: {}", path, e))?,
+        _ => ModelData::new(if is_ascii_stl(bytes) {
+                read_ascii_stl(&String::from_utf8_lossy(bytes)).map_err(|e| format!("{}: {}", path, e))?
+            } else {
+                let (vertices, stats) = read_binary_stl(path, bytes)?;
+                exact_stats = Some(stats);
+                vertices
+            }, false, false, false),
+    };
+    let has_facet_normals = match extension.as_ref().map(|extension| extension.as_str()) {
+        Some("obj") | Some("ply") => false,
+        _ => true,
+    };
+    Ok(finish_model(data, exact_stats, has_facet_normals))
+}
+
+fn finish_model(data: ModelData, exact_stats: Option<MeshStats>, has_facet_normals: bool) -> ModelData {
+    let mut maximum = 1.0f32;
+    for vertex in &data.vertices {
+        let p = vertex.position;
+        let step_max = p[0].abs().max(p[1].abs().max(p[2].abs()));
+        maximum = maximum.max(step_max);
+    }
+    let mut textured_model = vec![];
+    for vertex in &data.vertices {
+        textured_model.push(VertexModel {
+            tex_coords: if data.has_tex_coords {
+                vertex.tex_coords
+            } else {
+                [(vertex.tex_coords[0]/maximum + 1.0)/2.0, (vertex.tex_coords[1]/maximum + 1.0)/2.0]
+            },
+            .. *vertex
+        })
+    }
+    let report = validate_mesh(&data.vertices, WELD_TOLERANCE * maximum, has_facet_normals);
+    let (vertices, indices) = weld_vertices(&textured_model, WELD_TOLERANCE * maximum, data.has_tex_coords,
+                                              data.has_normals, data.has_colors);
+    let mut model = ModelData {
+        vertices: vertices, indices: indices, maximum: maximum, report: report, .. data
+    };
+    model.update_bounds();
+    model.stats = mesh_stats(&model, exact_stats);
+    model.bvh = Bvh::build(&model.vertices, &model.indices);
+    model
+}
+
+fn mesh_stats(model: &ModelData, exact_stats: Option<MeshStats>) -> MeshStats {
+    let mut stats = exact_stats.or_else(|| {
+        model_to_kernel_mesh("model", model).ok().map(|model_mesh| mesh_vertices(&model_mesh).1)
+    }).unwrap_or_else(|| float_stats(&model.vertices, &model.indices));
+    let size = model.size();
+    let positions: std::collections::HashSet<[u32; 3]> = model.vertices.iter().map(|vertex| {
+        [vertex.position[0].to_bits(), vertex.position[1].to_bits(), vertex.position[2].to_bits()]
+    }).collect();
+    stats.triangle_count = model.indices.len() / 3;
+    stats.vertex_count = positions.len();
+    stats.size = [size[0] as f64, size[1] as f64, size[2] as f64];
+    stats
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum BoolOp {
+    Union,
+    Intersection,
+    DifferenceAB,
+    DifferenceBA,
+}
+
+impl BoolOp {
+    fn names() -> [&'static str; 4] {
+        ["Union", "Intersection", "Difference A\u{2212}B", "Difference B\u{2212}A"]
+    }
+
+    fn from_name(name: &str) -> BoolOp {
+        match name {
+            "Intersection" => BoolOp::Intersection,
+            "Difference A\u{2212}B" => BoolOp::DifferenceAB,
+            "Difference B\u{2212}A" => BoolOp::DifferenceBA,
+            _ => BoolOp::Union,
+        }
+    }
+}
+
+// the kernel only reads binary STL, so other inputs are converted to a binary copy in memory
+fn load_kernel_mesh(path: &str) -> Result<Mesh, String> {
+    use std::io::Read;
+    let mut bytes = vec![];
+    File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let extension = std::path::Path::new(path).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let is_binary_stl = match extension.as_ref().map(|extension| extension.as_str()) {
+        Some("obj") | Some("ply") => false,
+        _ => !is_ascii_stl(&bytes),
+    };
+    if is_binary_stl {
+        return read_kernel_mesh(path, &bytes);
+    }
+    model_to_kernel_mesh(path, &make_model(path)?)
+}
+
+fn model_to_kernel_mesh(path: &str, model: &ModelData) -> Result<Mesh, String> {
+    let mut stl = vec![];
+    write_model(&mut stl, model, ExportFormat::BinaryStl, true).map_err(|e| format!("{}: {}", path, e))?;
+    read_kernel_mesh(path, &stl)
+}
+
+fn boolean_model(path_a: &str, path_b: &str, op: BoolOp) -> Result<ModelData, String> {
+    use geometry_kernel::boolean_op::BoolOpPerformer;
+    let mesh_a = load_kernel_mesh(path_a)?;
+    let mesh_b = load_kernel_mesh(path_b)?;
+    let result = match op {
+        BoolOp::Union => BoolOpPerformer::new(&mesh_a, &mesh_b).union(),
+        BoolOp::Intersection => BoolOpPerformer::new(&mesh_a, &mesh_b).intersection(),
+        BoolOp::DifferenceAB => BoolOpPerformer::new(&mesh_a, &mesh_b).difference(),
+        BoolOp::DifferenceBA => BoolOpPerformer::new(&mesh_b, &mesh_a).difference(),
+    };
+    let (vertices, stats) = mesh_vertices(&result);
+    if vertices.is_empty() {
+        return Err(format!("{} of the two meshes is empty", BoolOp::names()[op as usize]));
+    }
+    Ok(finish_model(ModelData::new(vertices, false, false, false), Some(stats), true))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ExportFormat {
+    BinaryStl,
+    AsciiStl,
+    Obj,
+    Ply,
+}
+
+impl ExportFormat {
+    fn names() -> [&'static str; 4] {
+        ["Binary STL", "ASCII STL", "OBJ", "PLY"]
+    }
+
+    fn from_name(name: &str) -> ExportFormat {
+        match name {
+            "ASCII STL" => ExportFormat::AsciiStl,
+            "OBJ" => ExportFormat::Obj,
+            "PLY" => ExportFormat::Ply,
+            _ => ExportFormat::BinaryStl,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            ExportFormat::BinaryStl | ExportFormat::AsciiStl => "stl",
+            ExportFormat::Obj => "obj",
+            ExportFormat::Ply => "ply",
+        }
+    }
+}
+
+fn write_model<W: std::io::Write>(out: &mut W, model: &ModelData, format: ExportFormat,
+                                  original_units: bool) -> std::io::Result<()> {
+    let scale = if original_units { 1.0 } else { 1.0 / model.maximum };
+    let positions: Vec<[f32; 3]> = model.vertices.iter().map(|vertex| {
+        [vertex.position[0]*scale, vertex.position[1]*scale, vertex.position[2]*scale]
+    }).collect();
+    let triangle_count = model.indices.len() / 3;
+    let triangle_points = |triangle: &[u32]| [
+        positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize],
+    ];
+    match format {
+        ExportFormat::BinaryStl => {
+            let mut header = [0u8; 80];
+            let title = b"binary STL exported by area_example";
+            header[..title.len()].copy_from_slice(title);
+            out.write_all(&header)?;
+            out.write_all(&(triangle_count as u32).to_le_bytes())?;
+            for triangle in model.indices.chunks(3) {
+                let points = triangle_points(triangle);
+                let normal = triangle_normal(&points);
+                for value in normal.iter().chain(points.iter().flat_map(|p| p.iter())) {
+                    out.write_all(&value.to_bits().to_le_bytes())?;
+                }
+                out.write_all(&[0, 0])?;
+            }
+        },
+        ExportFormat::AsciiStl => {
+            writeln!(out, "solid model")?;
+            for triangle in model.indices.chunks(3) {
+                let points = triangle_points(triangle);
+                let n = triangle_normal(&points);
+                writeln!(out, "  facet normal {} {} {}", n[0], n[1], n[2])?;
+                writeln!(out, "    outer loop")?;
+                for p in &points {
+                    writeln!(out, "      vertex {} {} {}", p[0], p[1], p[2])?;
+                }
+                writeln!(out, "    endloop")?;
+                writeln!(out, "  endfacet")?;
+            }
+            writeln!(out, "endsolid model")?;
+        },
+        ExportFormat::Obj => {
+            writeln!(out, "# exported by area_example")?;
+            for (p, vertex) in positions.iter().zip(model.vertices.iter()) {
+                writeln!(out, "v {} {} {}", p[0], p[1], p[2])?;
+                if model.has_tex_coords {
+                    writeln!(out, "vt {} {}", vertex.tex_coords[0], vertex.tex_coords[1])?;
+                }
+                if model.has_normals {
+                    writeln!(out, "vn {} {} {}", vertex.normal[0], vertex.normal[1], vertex.normal[2])?;
+                }
+            }
+            for triangle in model.indices.chunks(3) {
+                let (a, b, c) = (triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
+                match (model.has_tex_coords, model.has_normals) {
+                    (true, true) => writeln!(out, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?,
+                    (true, false) => writeln!(out, "f {0}/{0} {1}/{1} {2}/{2}", a, b, c)?,
+                    (false, true) => writeln!(out, "f {0}//{0} {1}//{1} {2}//{2}", a, b, c)?,
+                    (false, false) => writeln!(out, "f {} {} {}", a, b, c)?,
+                }
+            }
+        },
+        ExportFormat::Ply => {
+            writeln!(out, "ply")?;
+            writeln!(out, "format binary_little_endian 1.0")?;
+            writeln!(out, "comment exported by area_example")?;
+            writeln!(out, "element vertex {}", positions.len())?;
+            for name in &["x", "y", "z"] {
+                writeln!(out, "property float {}", name)?;
+            }
+            if model.has_normals {
+                for name in &["nx", "ny", "nz"] {
+                    writeln!(out, "property float {}", name)?;
+                }
+            }
+            if model.has_tex_coords {
+                writeln!(out, "property float u")?;
+                writeln!(out, "property float v")?;
+            }
+            if model.has_colors {
+                for name in &["red", "green", "blue", "alpha"] {
+                    writeln!(out, "property uchar {}", name)?;
+                }
+            }
+            writeln!(out, "element face {}", triangle_count)?;
+            writeln!(out, "property list uchar int vertex_indices")?;
+            writeln!(out, "end_header")?;
+            for (p, vertex) in positions.iter().zip(model.vertices.iter()) {
+                for value in p.iter().chain(vertex.normal.iter().take(if model.has_normals { 3 } else { 0 })) {
+                    out.write_all(&value.to_bits().to_le_bytes())?;
+                }
+                if model.has_tex_coords {
+                    for value in &vertex.tex_coords {
+                        out.write_all(&value.to_bits().to_le_bytes())?;
+                    }
+                }
+                if model.has_colors {
+                    for value in &vertex.color {
+                        out.write_all(&[(value.max(0.0).min(1.0) * 255.0).round() as u8])?;
+                    }
+                }
+            }
+            for triangle in model.indices.chunks(3) {
+                out.write_all(&[3])?;
+                for index in triangle {
+                    out.write_all(&(*index as i32).to_le_bytes())?;
+                }
+            }
+        },
+    }
+    out.flush()
+}
+
+fn export_model(path: &std::path::Path, model: &ModelData, format: ExportFormat,
+                original_units: bool) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut out = std::io::BufWriter::new(file);
+    write_model(&mut out, model, format, original_units).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Projection {
+    ModelUv,
+    PlanarX,
+    PlanarY,
+    PlanarZ,
+    Box,
+    Cylindrical,
+    Spherical,
+}
+
+impl Projection {
+    fn names() -> [&'static str; 7] {
+        ["model UV", "planar X", "planar Y", "planar Z", "box", "cylindrical", "spherical"]
+    }
+
+    fn from_name(name: &str) -> Projection {
+        match name {
+            "planar X" => Projection::PlanarX,
+            "planar Y" => Projection::PlanarY,
+            "planar Z" => Projection::PlanarZ,
+            "box" => Projection::Box,
+            "cylindrical" => Projection::Cylindrical,
+            "spherical" => Projection::Spherical,
+            _ => Projection::ModelUv,
+        }
+    }
+
+    fn index(&self) -> i32 {
+        match *self {
+            Projection::ModelUv => 0,
+            Projection::PlanarX => 1,
+            Projection::PlanarY => 2,
+            Projection::PlanarZ => 3,
+            Projection::Box => 4,
+            Projection::Cylindrical => 5,
+            Projection::Spherical => 6,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum TextureFilter {
+    Nearest,
+    Linear,
+    Trilinear,
+}
+
+impl TextureFilter {
+    fn names() -> [&'static str; 3] {
+        ["nearest", "linear", "trilinear"]
+    }
+
+    fn from_name(name: &str) -> TextureFilter {
+        match name {
+            "nearest" => TextureFilter::Nearest,
+            "linear" => TextureFilter::Linear,
+            _ => TextureFilter::Trilinear,
+        }
+    }
+
+    fn filters(&self) -> (glium::uniforms::MinifySamplerFilter, glium::uniforms::MagnifySamplerFilter) {
+        use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+        match *self {
+            TextureFilter::Nearest => (MinifySamplerFilter::Nearest, MagnifySamplerFilter::Nearest),
+            TextureFilter::Linear => (MinifySamplerFilter::Linear, MagnifySamplerFilter::Linear),
+            TextureFilter::Trilinear => (MinifySamplerFilter::LinearMipmapLinear, MagnifySamplerFilter::Linear),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum TextureWrap {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl TextureWrap {
+    fn names() -> [&'static str; 3] {
+        ["repeat", "mirror", "clamp"]
+    }
+
+    fn from_name(name: &str) -> TextureWrap {
+        match name {
+            "mirror" => TextureWrap::Mirror,
+            "clamp" => TextureWrap::Clamp,
+            _ => TextureWrap::Repeat,
+        }
+    }
+
+    fn function(&self) -> glium::uniforms::SamplerWrapFunction {
+        match *self {
+            TextureWrap::Repeat => glium::uniforms::SamplerWrapFunction::Repeat,
+            TextureWrap::Mirror => glium::uniforms::SamplerWrapFunction::Mirror,
+            TextureWrap::Clamp => glium::uniforms::SamplerWrapFunction::Clamp,
+        }
+    }
+}
+
+// the format is detected from the contents; TGA has no signature, so it is only tried by extension
+fn decode_image(buf: &[u8], path: &std::path::Path) -> Result<image::RgbaImage, String> {
+    let is_tga = path.extension().and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase() == "tga").unwrap_or(false);
+    let image = if is_tga {
+        image::load_from_memory_with_format(buf, image::TGA)
+    } else {
+        image::load_from_memory(buf)
+    };
+    image.map(|image| image.to_rgba()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum TextureSource {
+    File(std::path::PathBuf),
+    Library(&'static str),
+}
+
+const TEXTURE_LIBRARY: [&'static str; 8] = ["t2", "t3", "t4", "checkerboard", "UV grid", "noise", "wood", "knurl"];
+
+fn hash_noise(x: i32, y: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(374761393) ^ (y as u32).wrapping_mul(668265263);
+    h = (h ^ (h >> 13)).wrapping_mul(1274126177);
+    (h ^ (h >> 16)) as f32 / std::u32::MAX as f32
+}
+
+// smooth value noise that repeats every `period` cells, summed over a few octaves
+fn fractal_noise(x: f32, y: f32, period: i32) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut period = period;
+    let (mut x, mut y) = (x * period as f32, y * period as f32);
+    for _ in 0..4 {
+        let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let (sx, sy) = (fx*fx*(3.0 - 2.0*fx), fy*fy*(3.0 - 2.0*fy));
+        let h = |i: i32, j: i32| hash_noise((x0 + i).rem_euclid(period), (y0 + j).rem_euclid(period));
+        let top = h(0, 0) + (h(1, 0) - h(0, 0))*sx;
+        let bottom = h(0, 1) + (h(1, 1) - h(0, 1))*sx;
+        value += (top + (bottom - top)*sy)*amplitude;
+        amplitude *= 0.5;
+        period *= 2;
+        x *= 2.0;
+        y *= 2.0;
+    }
+    value / 0.9375
+}
+
+fn library_image(name: &str) -> image::RgbaImage {
+    let size = 256;
+    let generate = |f: &dyn Fn(f32, f32, u32, u32) -> [f32; 3]| image::ImageBuffer::from_fn(size, size, |x, y| {
+        let color = f((x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32, x, y);
+        image::Rgba([(color[0].max(0.0).min(1.0)*255.0) as u8, (color[1].max(0.0).min(1.0)*255.0) as u8,
+                     (color[2].max(0.0).min(1.0)*255.0) as u8, 255])
+    });
+    match name {
+        "t2" => decode_image(&include_bytes!("t2.jpg")[..], std::path::Path::new("t2.jpg")).unwrap(),
+        "t3" => decode_image(&include_bytes!("t3.jpg")[..], std::path::Path::new("t3.jpg")).unwrap(),
+        "t4" => decode_image(&include_bytes!("t4.jpg")[..], std::path::Path::new("t4.jpg")).unwrap(),
+        "checkerboard" => generate(&|_, _, x, y| if (x / 32 + y / 32) % 2 == 0 { [0.9; 3] } else { [0.2; 3] }),
+        "UV grid" => generate(&|u, v, x, y| {
+            if x % 128 < 3 || y % 128 < 3 {
+                [0.0; 3]
+            } else if x % 32 == 0 || y % 32 == 0 {
+                [0.25; 3]
+            } else {
+                let cell = if (x / 32 + y / 32) % 2 == 0 { 1.0 } else { 0.8 };
+                [(0.3 + 0.7*u)*cell, (0.3 + 0.7*(1.0 - v))*cell, 0.6*cell]
+            }
+        }),
+        "noise" => generate(&|u, v, _, _| [fractal_noise(u, v, 8); 3]),
+        "knurl" => generate(&|u, v, _, _| {
+            // diamond knurl as a height map: two crossed sets of ridges
+            let ridge = |t: f32| 1.0 - ((t*8.0).fract() - 0.5).abs()*2.0;
+            [ridge(u + v).min(ridge(u - v + 1.0)); 3]
+        }),
+        _ => generate(&|u, v, _, _| {
+            let (dx, dy) = (u - 0.5, v - 0.5);
+            let ring = ((dx*dx + dy*dy).sqrt()*16.0 + fractal_noise(u, v, 4)*1.5).fract();
+            let t = (ring*std::f32::consts::PI*2.0).sin()*0.5 + 0.5;
+            [0.55 + 0.23*t, 0.35 + 0.21*t, 0.17 + 0.15*t]
+        }),
+    }
+}
+
+// how texture coordinates are derived in the fragment shader; rotation is in degrees
+#[derive(Copy, Clone)]
+struct TextureMapping {
+    projection: Projection,
+    scale: [f32; 2],
+    offset: [f32; 2],
+    rotation: f32,
+}
+
+impl TextureMapping {
+    fn for_model(model: &ModelData) -> TextureMapping {
+        TextureMapping {
+            projection: if model.has_tex_coords { Projection::ModelUv } else { Projection::Box },
+            scale: [1.0, 1.0],
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum DetailKind {
+    Off,
+    NormalMap,
+    BumpMap,
+}
+
+impl DetailKind {
+    fn names() -> [&'static str; 3] {
+        ["off", "normal map", "bump map"]
+    }
+
+    fn from_name(name: &str) -> DetailKind {
+        match name {
+            "normal map" => DetailKind::NormalMap,
+            "bump map" => DetailKind::BumpMap,
+            _ => DetailKind::Off,
+        }
+    }
+
+    fn index(&self) -> i32 {
+        match *self {
+            DetailKind::Off => 0,
+            DetailKind::NormalMap => 1,
+            DetailKind::BumpMap => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Shading {
+    Flat,
+    Smooth,
+    AutoSmooth,
+}
+
+impl Shading {
+    fn names() -> [&'static str; 3] {
+        ["flat", "smooth", "auto-smooth"]
+    }
+
+    fn from_name(name: &str) -> Shading {
+        match name {
+            "smooth" => Shading::Smooth,
+            "auto-smooth" => Shading::AutoSmooth,
+            _ => Shading::Flat,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RenderMode {
+    Shaded,
+    Wireframe,
+    ShadedEdges,
+    HiddenLine,
+    Points,
+}
+
+impl RenderMode {
+    fn names() -> [&'static str; 5] {
+        ["shaded", "wireframe", "shaded with edges", "hidden line", "points"]
+    }
+
+    fn from_name(name: &str) -> RenderMode {
+        match name {
+            "wireframe" => RenderMode::Wireframe,
+            "shaded with edges" => RenderMode::ShadedEdges,
+            "hidden line" => RenderMode::HiddenLine,
+            "points" => RenderMode::Points,
+            _ => RenderMode::Shaded,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Clone)]
+struct ShadedMesh {
+    vertices: Vec<VertexModel>,
+    indices: Vec<u32>,
+}
+
+fn shade_model(model: &ModelData, shading: Shading, crease_angle: f32) -> ShadedMesh {
+    use std::collections::HashMap;
+    if shading == Shading::Flat || (shading == Shading::Smooth && model.has_normals) {
+        let mut vertices = model.vertices.clone();
+        compute_tangents(&mut vertices, &model.indices);
+        return ShadedMesh { vertices: vertices, indices: model.indices.clone() };
+    }
+    let mut position_ids = HashMap::new();
+    let vertex_positions: Vec<usize> = model.vertices.iter().map(|vertex| {
+        let p = vertex.position;
+        let key = [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+        let next = position_ids.len();
+        *position_ids.entry(key).or_insert(next)
+    }).collect();
+    let face_normals: Vec<[f32; 3]> = model.indices.chunks(3).map(|triangle| {
+        let a = model.vertices[triangle[0] as usize].position;
+        let b = model.vertices[triangle[1] as usize].position;
+        let c = model.vertices[triangle[2] as usize].position;
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]]
+    }).collect();
+    let unit = |n: [f32; 3]| {
+        let length = (n[0]*n[0] + n[1]*n[1] + n[2]*n[2]).sqrt();
+        if length > 0.0 { [n[0]/length, n[1]/length, n[2]/length] } else { n }
+    };
+    // faces count by their angle at the corner, so how a polygon was split into triangles does not
+    // tilt the normal
+    let mut incident_faces = vec![vec![]; position_ids.len()];
+    for (i, &index) in model.indices.iter().enumerate() {
+        let triangle = &model.indices[i - i % 3..i - i % 3 + 3];
+        let p = model.vertices[index as usize].position;
+        let a = model.vertices[triangle[(i + 1) % 3] as usize].position;
+        let b = model.vertices[triangle[(i + 2) % 3] as usize].position;
+        let (u, v) = (unit([a[0] - p[0], a[1] - p[1], a[2] - p[2]]), unit([b[0] - p[0], b[1] - p[1], b[2] - p[2]]));
+        let angle = dot3(u, v).max(-1.0).min(1.0).acos();
+        incident_faces[vertex_positions[index as usize]].push((i / 3, angle));
+    }
+    let cos_crease = crease_angle.to_radians().cos();
+    let mut vertices = vec![];
+    let mut indices = Vec::with_capacity(model.indices.len());
+    let mut lookup = HashMap::new();
+    for (i, &index) in model.indices.iter().enumerate() {
+        let face = unit(face_normals[i / 3]);
+        let mut normal = [0.0f32; 3];
+        for &(other, angle) in &incident_faces[vertex_positions[index as usize]] {
+            let m = unit(face_normals[other]);
+            if shading == Shading::Smooth || face[0]*m[0] + face[1]*m[1] + face[2]*m[2] >= cos_crease {
+                normal = [normal[0] + m[0]*angle, normal[1] + m[1]*angle, normal[2] + m[2]*angle];
+            }
+        }
+        let normal = unit(normal);
+        let key = (index, [normal[0].to_bits(), normal[1].to_bits(), normal[2].to_bits()]);
+        let new_index = *lookup.entry(key).or_insert_with(|| {
+            vertices.push(VertexModel { normal: normal, .. model.vertices[index as usize] });
+            (vertices.len() - 1) as u32
+        });
+        indices.push(new_index);
+    }
+    compute_tangents(&mut vertices, &indices);
+    ShadedMesh { vertices: vertices, indices: indices }
+}
+
+// per-vertex tangents from the UV gradients of the incident triangles, orthogonal to the normal;
+// w is the handedness of the (tangent, bitangent, normal) frame
+fn compute_tangents(vertices: &mut [VertexModel], indices: &[u32]) {
+    let mut tangents = vec![[0.0f32; 3]; vertices.len()];
+    let mut bitangents = vec![[0.0f32; 3]; vertices.len()];
+    for triangle in indices.chunks(3) {
+        let (a, b, c) = (&vertices[triangle[0] as usize], &vertices[triangle[1] as usize], &vertices[triangle[2] as usize]);
+        let e1 = [b.position[0] - a.position[0], b.position[1] - a.position[1], b.position[2] - a.position[2]];
+        let e2 = [c.position[0] - a.position[0], c.position[1] - a.position[1], c.position[2] - a.position[2]];
+        let (du1, dv1) = (b.tex_coords[0] - a.tex_coords[0], b.tex_coords[1] - a.tex_coords[1]);
+        let (du2, dv2) = (c.tex_coords[0] - a.tex_coords[0], c.tex_coords[1] - a.tex_coords[1]);
+        let det = du1*dv2 - du2*dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1.0 / det;
+        let t = [(e1[0]*dv2 - e2[0]*dv1)*r, (e1[1]*dv2 - e2[1]*dv1)*r, (e1[2]*dv2 - e2[2]*dv1)*r];
+        let b = [(e2[0]*du1 - e1[0]*du2)*r, (e2[1]*du1 - e1[1]*du2)*r, (e2[2]*du1 - e1[2]*du2)*r];
+        for &index in triangle {
+            for i in 0..3 {
+                tangents[index as usize][i] += t[i];
+                bitangents[index as usize][i] += b[i];
+            }
+        }
+    }
+    for (vertex, (t, b)) in vertices.iter_mut().zip(tangents.iter().zip(bitangents.iter())) {
+        let n = normalize3(vertex.normal);
+        let along = dot3(n, *t);
+        let tangent = normalize3([t[0] - n[0]*along, t[1] - n[1]*along, t[2] - n[2]*along]);
+        let tangent = if dot3(tangent, tangent) > 0.0 { tangent } else { plane_basis(n).0 };
+        let handedness = if dot3(cross3(n, tangent), *b) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = [tangent[0], tangent[1], tangent[2], handedness];
+    }
+}
+
+fn model_counts(mesh: &ShadedMesh) -> String {
+    format!("vertices: {}, indices: {}", mesh.vertices.len(), mesh.indices.len())
+}
+
+fn model_size(model: &ModelData) -> String {
+    let size = model.size();
+    format!("size: {:.2} \u{d7} {:.2} \u{d7} {:.2} mm", size[0], size[1], size[2])
+}
+
+// area, volume and centroid summed in floating point because the kernel could not read the mesh are marked approximate
+fn stats_rows(stats: &MeshStats) -> [(&'static str, String); 6] {
+    let approx = if stats.is_exact || stats.triangle_count == 0 { "" } else { " (approx.)" };
+    [("triangles", format!("{}", stats.triangle_count)),
+     ("vertices", format!("{}", stats.vertex_count)),
+     ("area", format!("{:.3} mm\u{b2}{}", stats.area, approx)),
+     ("volume", format!("{:.3} mm\u{b3}{}", stats.volume, approx)),
+     ("size", format!("{:.3} \u{d7} {:.3} \u{d7} {:.3} mm", stats.size[0], stats.size[1], stats.size[2])),
+     ("centroid", format!("{:.3}, {:.3}, {:.3}{}", stats.centroid[0], stats.centroid[1], stats.centroid[2], approx))]
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0]*b[0] + a[1]*b[1] + a[2]*b[2]
+}
+
+fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2], a[0]*b[1] - a[1]*b[0]]
+}
+
+fn normalize3(a: [f32; 3]) -> [f32; 3] {
+    let length = dot3(a, a).sqrt();
+    if length > 0.0 {
+        [a[0]/length, a[1]/length, a[2]/length]
+    } else {
+        a
+    }
+}
+
+// columns of Rz * Ry * Rx for angles in degrees
+fn rotation_matrix(rotation: [f32; 3]) -> [[f32; 3]; 3] {
+    let (sx, cx) = rotation[0].to_radians().sin_cos();
+    let (sy, cy) = rotation[1].to_radians().sin_cos();
+    let (sz, cz) = rotation[2].to_radians().sin_cos();
+    [[cy*cz, cy*sz, -sy],
+     [sx*sy*cz - cx*sz, sx*sy*sz + cx*cz, sx*cy],
+     [cx*sy*cz + sx*sz, cx*sy*sz - sx*cz, cx*cy]]
+}
+
+fn euler_angles(r: &[[f32; 3]; 3]) -> [f32; 3] {
+    let y = (-r[0][2]).max(-1.0).min(1.0).asin();
+    let (x, z) = if y.cos() > 1e-5 {
+        (r[1][2].atan2(r[2][2]), r[0][1].atan2(r[0][0]))
+    } else {
+        (0.0, (-r[1][0]).atan2(r[1][1]))
+    };
+    [x.to_degrees(), y.to_degrees(), z.to_degrees()]
+}
+
+fn mat3_mul(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut m = [[0.0f32; 3]; 3];
+    for col in 0..3 {
+        for row in 0..3 {
+            m[col][row] = (0..3).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    m
+}
+
+fn mat4_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut m = [[0.0f32; 4]; 4];
+    for col in 0..4 {
+        for row in 0..4 {
+            m[col][row] = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    m
+}
+
+fn transform_point(m: &[[f32; 4]; 4], p: [f32; 3]) -> [f32; 3] {
+    let mut q = [0.0f32; 3];
+    for row in 0..3 {
+        q[row] = m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+    }
+    q
+}
+
+struct SceneObject {
+    id: u64,
+    revision: u64,
+    name: String,
+    model: ModelData,
+    shaded: ShadedMesh,
+    translation: [f32; 3],
+    rotation: [f32; 3],
+    scale: [f32; 3],
+    color: [f32; 4],
+    texture: Option<TextureSource>,
+    mapping: TextureMapping,
+    detail_map: Option<TextureSource>,
+    detail_kind: DetailKind,
+    detail_strength: f32,
+    is_visible: bool,
+}
+
+impl SceneObject {
+    // scale and rotate (X, then Y, then Z, in degrees) about the model center, then translate
+    fn model_matrix(&self) -> [[f32; 4]; 4] {
+        let mut columns = rotation_matrix(self.rotation);
+        for i in 0..3 {
+            for row in 0..3 {
+                columns[i][row] *= self.scale[i];
+            }
+        }
+        let c = self.model.center();
+        let mut m = [[0.0f32; 4]; 4];
+        for i in 0..3 {
+            m[i] = [columns[i][0], columns[i][1], columns[i][2], 0.0];
+            m[3][i] = self.translation[i] + c[i] - (columns[0][i]*c[0] + columns[1][i]*c[1] + columns[2][i]*c[2]);
+        }
+        m[3][3] = 1.0;
+        m
+    }
+
+    fn world_bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let m = self.model_matrix();
+        let (lo, hi) = (self.model.bbox_min, self.model.bbox_max);
+        let mut bounds = ([std::f32::MAX; 3], [std::f32::MIN; 3]);
+        for corner in 0..8 {
+            let p = transform_point(&m, [if corner & 1 == 0 { lo[0] } else { hi[0] },
+                                         if corner & 2 == 0 { lo[1] } else { hi[1] },
+                                         if corner & 4 == 0 { lo[2] } else { hi[2] }]);
+            for i in 0..3 {
+                bounds.0[i] = bounds.0[i].min(p[i]);
+                bounds.1[i] = bounds.1[i].max(p[i]);
+            }
+        }
+        bounds
+    }
+
+    fn pivot(&self) -> [f32; 3] {
+        let c = self.model.center();
+        [c[0] + self.translation[0], c[1] + self.translation[1], c[2] + self.translation[2]]
+    }
+
+    // a copy of the model with the transform applied to its vertices
+    fn baked_model(&self) -> ModelData {
+        let m = self.model_matrix();
+        let vertices: Vec<_> = self.model.vertices.iter().map(|vertex| VertexModel {
+            position: transform_point(&m, vertex.position),
+            normal: self.normal_to_world(vertex.normal),
+            .. *vertex
+        }).collect();
+        let mut report = self.model.report.clone();
+        for p in report.problem_triangles.iter_mut().chain(report.problem_edges.iter_mut()) {
+            *p = transform_point(&m, *p);
+        }
+        let mut maximum = 1.0f32;
+        for vertex in &vertices {
+            let p = vertex.position;
+            maximum = maximum.max(p[0].abs().max(p[1].abs().max(p[2].abs())));
+        }
+        let mut model = ModelData {
+            indices: self.model.indices.clone(), maximum: maximum, report: report,
+            .. ModelData::new(vertices, self.model.has_tex_coords, self.model.has_normals, self.model.has_colors)
+        };
+        model.update_bounds();
+        model.stats = mesh_stats(&model, None);
+        model.bvh = Bvh::build(&model.vertices, &model.indices);
+        model
+    }
+
+    fn normal_to_world(&self, n: [f32; 3]) -> [f32; 3] {
+        let r = rotation_matrix(self.rotation);
+        let s = self.scale;
+        let n = [n[0] / s[0], n[1] / s[1], n[2] / s[2]];
+        normalize3([r[0][0]*n[0] + r[1][0]*n[1] + r[2][0]*n[2],
+                    r[0][1]*n[0] + r[1][1]*n[1] + r[2][1]*n[2],
+                    r[0][2]*n[0] + r[1][2]*n[1] + r[2][2]*n[2]])
+    }
+
+    // the ray in model coordinates, keeping the ray parameter of every point
+    fn ray_to_local(&self, origin: [f32; 3], dir: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        let m = self.model_matrix();
+        let r = rotation_matrix(self.rotation);
+        let s = self.scale;
+        let to_local = |v: [f32; 3]| [dot3(r[0], v) / s[0], dot3(r[1], v) / s[1], dot3(r[2], v) / s[2]];
+        (to_local([origin[0] - m[3][0], origin[1] - m[3][1], origin[2] - m[3][2]]), to_local(dir))
+    }
+}
+
+struct ModelState {
+    objects: Vec<SceneObject>,
+    selected: Option<usize>,
+    next_id: u64,
+    shading: Shading,
+    crease_angle: f32,
+    generation: u64,
+    transform_revision: u64,
+    is_fit_pending: bool,
+    is_render : bool,
+    error: Option<String>,
+    boolean_result: Option<u64>,
+    shading_request: u64,
+    is_reshading: bool,
+}
+
+impl ModelState {
+    fn add_object(&mut self, name: String, model: ModelData, shaded: ShadedMesh) -> u64 {
+        self.generation += 1;
+        self.next_id += 1;
+        let mapping = TextureMapping::for_model(&model);
+        self.objects.push(SceneObject {
+            id: self.next_id, revision: self.generation, name: name, model: model, shaded: shaded,
+            translation: [0.0; 3], rotation: [0.0; 3], scale: [1.0; 3], color: [1.0, 1.0, 1.0, 1.0],
+            texture: None, mapping: mapping, detail_map: None, detail_kind: DetailKind::Off, detail_strength: 1.0,
+            is_visible: true,
+        });
+        self.selected = Some(self.objects.len() - 1);
+        self.next_id
+    }
+
+    fn duplicate_selected(&mut self) {
+        if let Some(selected) = self.selected {
+            let object = &self.objects[selected];
+            let copy = SceneObject {
+                id: self.next_id + 1, name: format!("{} copy", object.name),
+                model: object.model.clone(), shaded: object.shaded.clone(),
+                texture: object.texture.clone(), detail_map: object.detail_map.clone(), .. *object
+            };
+            self.next_id += 1;
+            self.generation += 1;
+            self.objects.push(copy);
+            self.selected = Some(self.objects.len() - 1);
+        }
+    }
+
+    fn bake_selected(&mut self) {
+        if let Some(selected) = self.selected {
+            self.generation += 1;
+            self.transform_revision += 1;
+            let (shading, crease_angle, generation) = (self.shading, self.crease_angle, self.generation);
+            let object = &mut self.objects[selected];
+            object.model = object.baked_model();
+            object.shaded = shade_model(&object.model, shading, crease_angle);
+            object.translation = [0.0; 3];
+            object.rotation = [0.0; 3];
+            object.scale = [1.0; 3];
+            object.revision = generation;
+        }
+    }
+
+    fn delete_selected(&mut self) {
+        if let Some(selected) = self.selected {
+            self.objects.remove(selected);
+            self.generation += 1;
+            self.selected = if self.objects.is_empty() {
+                None
+            } else {
+                Some(selected.min(self.objects.len() - 1))
+            };
+        }
+    }
+
+    fn selected_object(&self) -> Option<&SceneObject> {
+        self.selected.map(|selected| &self.objects[selected])
+    }
+
+    fn selected_object_mut(&mut self) -> Option<&mut SceneObject> {
+        match self.selected {
+            Some(selected) => Some(&mut self.objects[selected]),
+            None => None,
+        }
+    }
+
+    // nearest visible object and triangle under a scene space ray, with the ray parameter of the hit
+    fn pick(&self, origin: [f32; 3], dir: [f32; 3]) -> Option<(usize, usize, f32)> {
+        let mut best: Option<(usize, usize, f32)> = None;
+        for (i, object) in self.objects.iter().enumerate().filter(|&(_, object)| object.is_visible) {
+            let (local_origin, local_dir) = object.ray_to_local(origin, dir);
+            let hit = object.model.bvh.intersect(&object.model.vertices, &object.model.indices,
+                                                 local_origin, local_dir);
+            if let Some((triangle, t)) = hit {
+                if best.map(|best| t < best.2).unwrap_or(true) {
+                    best = Some((i, triangle, t));
+                }
+            }
+        }
+        best
+    }
+
+    // center and radius of all visible objects, used to fit the camera and place the light
+    fn bounds(&self) -> ([f32; 3], f32) {
+        let mut bounds = ([std::f32::MAX; 3], [std::f32::MIN; 3]);
+        for object in self.objects.iter().filter(|object| object.is_visible) {
+            let (lo, hi) = object.world_bounds();
+            for i in 0..3 {
+                bounds.0[i] = bounds.0[i].min(lo[i]);
+                bounds.1[i] = bounds.1[i].max(hi[i]);
+            }
+        }
+        let (lo, hi) = bounds;
+        if lo[0] > hi[0] {
+            return ([0.0; 3], 1.0);
+        }
+        let d = [hi[0] - lo[0], hi[1] - lo[1], hi[2] - lo[2]];
+        ([(lo[0] + hi[0]) / 2.0, (lo[1] + hi[1]) / 2.0, (lo[2] + hi[2]) / 2.0],
+         ((d[0]*d[0] + d[1]*d[1] + d[2]*d[2]).sqrt() / 2.0).max(1e-3))
+    }
+}
+
+fn spawn_model_loader(path: std::path::PathBuf, model_state: std::sync::Arc<std::sync::Mutex<ModelState>>) {
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    spawn_model_job(name, move || make_model(path.to_str().unwrap()), false, model_state);
+}
+
+fn spawn_model_job<F>(name: String, job: F, is_boolean: bool, model_state: std::sync::Arc<std::sync::Mutex<ModelState>>)
+    where F: FnOnce() -> Result<ModelData, String> + Send + 'static {
+    use std::thread;
+    thread::spawn(move || {
+        let (shading, crease_angle) = {
+            let mut model_state = model_state.lock().unwrap();
+            model_state.is_render = true;
+            (model_state.shading, model_state.crease_angle)
+        };
+        let result = job()
+            .map(|model| {
+                let shaded = shade_model(&model, shading, crease_angle);
+                (model, shaded)
+            });
+        let mut model_state = model_state.lock().unwrap();
+        match result {
+            Ok((model, shaded)) => if model_state.is_render {
+                let id = model_state.add_object(name, model, shaded);
+                if is_boolean {
+                    model_state.boolean_result = Some(id);
+                }
+                model_state.is_fit_pending = true;
+            },
+            Err(error) => model_state.error = Some(error),
+        }
+        model_state.is_render = false;
+    });
+}
+
+// reshades every object off the GTK thread; results are dropped if the shading was changed
+// again or the object was rebuilt in the meantime
+fn spawn_reshade(model_state: std::sync::Arc<std::sync::Mutex<ModelState>>) {
+    use std::thread;
+    let (request, shading, crease_angle, models) = {
+        let mut model_state = model_state.lock().unwrap();
+        model_state.shading_request += 1;
+        model_state.is_reshading = true;
+        let models: Vec<_> = model_state.objects.iter()
+            .map(|object| (object.id, object.revision, object.model.clone())).collect();
+        (model_state.shading_request, model_state.shading, model_state.crease_angle, models)
+    };
+    thread::spawn(move || {
+        let shaded: Vec<_> = models.into_iter().map(|(id, revision, model)| {
+            (id, revision, shade_model(&model, shading, crease_angle))
+        }).collect();
+        let mut model_state = model_state.lock().unwrap();
+        if model_state.shading_request != request {
+            return;
+        }
+        model_state.generation += 1;
+        let generation = model_state.generation;
+        for (id, revision, shaded) in shaded {
+            if let Some(object) = model_state.objects.iter_mut().find(|object| object.id == id) {
+                if object.revision == revision {
+                    object.shaded = shaded;
+                    object.revision = generation;
+                }
+            }
+        }
+        model_state.is_reshading = false;
+    });
+}
+
+fn mat4_array(m: &glm::Mat4) -> [[f32; 4]; 4] {
+    let columns = m.as_array();
+    [*columns[0].as_array(), *columns[1].as_array(), *columns[2].as_array(), *columns[3].as_array()]
+}
+
+fn translation_scale(t: [f32; 3], s: f32) -> [[f32; 4]; 4] {
+    [[s, 0.0, 0.0, 0.0], [0.0, s, 0.0, 0.0], [0.0, 0.0, s, 0.0], [t[0], t[1], t[2], 1.0]]
+}
+
+// projection * view of a shadow camera at the light that sees the sphere (center, extent); a light
+// inside the sphere keeps its position and gets the widest usable field of view instead
+fn light_space_matrix(light: &Light, position: [f32; 3], center: [f32; 3], extent: f32) -> [[f32; 4]; 4] {
+    let (eye, forward, projection) = if light.kind == LightKind::Directional {
+        let d = normalize3(light.direction);
+        let eye = [center[0] - d[0]*2.0*extent, center[1] - d[1]*2.0*extent, center[2] - d[2]*2.0*extent];
+        let (near, far) = (extent, 3.0*extent);
+        (eye, d, [[1.0 / extent, 0.0, 0.0, 0.0],
+                  [0.0, 1.0 / extent, 0.0, 0.0],
+                  [0.0, 0.0, -2.0 / (far - near), 0.0],
+                  [0.0, 0.0, -(far + near) / (far - near), 1.0]])
+    } else {
+        let offset = [center[0] - position[0], center[1] - position[1], center[2] - position[2]];
+        let distance = dot3(offset, offset).sqrt();
+        let forward = if distance > extent*1e-3 {
+            normalize3(offset)
+        } else if light.kind == LightKind::Spot && dot3(light.direction, light.direction) > 0.0 {
+            normalize3(light.direction)
+        } else {
+            [0.0, -1.0, 0.0]
+        };
+        let fov = (2.0*(extent / distance.max(1e-6)).min(1.0).asin()).min(170.0f32.to_radians());
+        let near = if distance > extent { (distance - extent).max(extent*0.01) } else { extent*1e-3 };
+        (position, forward, mat4_array(&glm::ext::perspective_rh(fov, 1.0, near, distance + extent)))
+    };
+    let up = if forward[1].abs() > 0.99 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+    let view = glm::ext::look_at(glm::vec3(eye[0], eye[1], eye[2]),
+                                 glm::vec3(eye[0] + forward[0], eye[1] + forward[1], eye[2] + forward[2]),
+                                 glm::vec3(up[0], up[1], up[2]));
+    mat4_mul(&projection, &mat4_array(&view))
+}
+
+struct Camera {
+    target: [f32; 3],
+    distance: f32,
+    rx: f32,
+    ry: f32,
+    fov: f32,
+    is_ortho: bool,
+}
+
+const VIEW_PRESETS: [(&'static str, f32, f32); 7] = [
+    ("Front", 0.0, 0.0), ("Back", 0.0, 180.0), ("Left", 0.0, 90.0), ("Right", 0.0, -90.0),
+    ("Top", 90.0, 0.0), ("Bottom", -90.0, 0.0), ("Isometric", 35.264, -45.0),
+];
+
+impl Camera {
+    fn eye(&self) -> [f32; 3] {
+        let (rx, ry) = (self.rx.to_radians(), self.ry.to_radians());
+        [self.target[0] - self.distance * rx.cos() * ry.sin(),
+         self.target[1] + self.distance * rx.sin(),
+         self.target[2] + self.distance * rx.cos() * ry.cos()]
+    }
+
+    fn up(&self) -> [f32; 3] {
+        let (rx, ry) = (self.rx.to_radians(), self.ry.to_radians());
+        [rx.sin() * ry.sin(), rx.cos(), -rx.sin() * ry.cos()]
+    }
+
+    fn right(&self) -> [f32; 3] {
+        let ry = self.ry.to_radians();
+        [ry.cos(), 0.0, ry.sin()]
+    }
+
+    fn view_matrix(&self) -> glm::Mat4 {
+        let (eye, up, target) = (self.eye(), self.up(), self.target);
+        glm::ext::look_at(glm::vec3(eye[0], eye[1], eye[2]),
+                          glm::vec3(target[0], target[1], target[2]),
+                          glm::vec3(up[0], up[1], up[2]))
+    }
+
+    fn projection_matrix(&self, aspect: f32) -> [[f32; 4]; 4] {
+        if self.is_ortho {
+            let half_height = self.half_height();
+            let (near, far) = (-self.distance * 100.0, self.distance * 100.0);
+            [[1.0 / (half_height * aspect), 0.0, 0.0, 0.0],
+             [0.0, 1.0 / half_height, 0.0, 0.0],
+             [0.0, 0.0, -2.0 / (far - near), 0.0],
+             [0.0, 0.0, -(far + near) / (far - near), 1.0]]
+        } else {
+            mat4_array(&glm::ext::perspective_rh(glm::radians(self.fov), aspect,
+                                                 self.distance / 100.0, self.distance * 100.0))
+        }
+    }
+
+    fn half_height(&self) -> f32 {
+        self.distance * (self.fov.to_radians() / 2.0).tan()
+    }
+
+    fn fit(&mut self, center: [f32; 3], radius: f32) {
+        self.target = center;
+        self.distance = radius * 1.1 / (self.fov.to_radians() / 2.0).sin();
+    }
+
+    fn step_towards(&mut self, rx: f32, ry: f32) -> bool {
+        let wrap = |angle: f32| (angle + 180.0).rem_euclid(360.0) - 180.0;
+        let (drx, dry) = (wrap(rx - self.rx), wrap(ry - self.ry));
+        if drx.abs() < 0.5 && dry.abs() < 0.5 {
+            self.rx += drx;
+            self.ry += dry;
+            return true;
+        }
+        self.rx += drx * 0.25;
+        self.ry += dry * 0.25;
+        false
+    }
+
+    fn orbit(&mut self, dx: f32, dy: f32) {
+        self.ry += dx * 0.5;
+        self.rx += dy * 0.5;
+    }
+
+    fn pan(&mut self, dx: f32, dy: f32, height: f32) {
+        let step = 2.0 * self.half_height() / height;
+        let (right, up) = (self.right(), self.up());
+        for i in 0..3 {
+            self.target[i] += (up[i] * dy - right[i] * dx) * step;
+        }
+    }
+
+    fn zoom(&mut self, factor: f32, x: f32, y: f32, width: f32, height: f32) {
+        let half_height = self.half_height();
+        let half_width = half_height * width / height;
+        let (nx, ny) = (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+        let (right, up) = (self.right(), self.up());
+        for i in 0..3 {
+            let offset = right[i] * nx * half_width + up[i] * ny * half_height;
+            self.target[i] += offset * (1.0 - factor);
+        }
+        self.distance *= factor;
+    }
+
+    // world space ray through the widget point (x, y)
+    fn ray(&self, x: f32, y: f32, width: f32, height: f32) -> ([f32; 3], [f32; 3]) {
+        let half_height = self.half_height();
+        let half_width = half_height * width / height;
+        let (nx, ny) = (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+        let (eye, right, up) = (self.eye(), self.right(), self.up());
+        let forward = normalize3([self.target[0] - eye[0], self.target[1] - eye[1], self.target[2] - eye[2]]);
+        let mut offset = [0.0f32; 3];
+        for i in 0..3 {
+            offset[i] = right[i] * nx * half_width + up[i] * ny * half_height;
+        }
+        if self.is_ortho {
+            let back = self.distance * 100.0;
+            ([eye[0] + offset[0] - forward[0] * back, eye[1] + offset[1] - forward[1] * back,
+              eye[2] + offset[2] - forward[2] * back], forward)
+        } else {
+            (eye, normalize3([forward[0] * self.distance + offset[0], forward[1] * self.distance + offset[1],
+                              forward[2] * self.distance + offset[2]]))
+        }
+    }
+
+    // half of the visible height at the depth of `p`
+    fn half_height_at(&self, p: [f32; 3]) -> f32 {
+        if self.is_ortho {
+            return self.half_height();
+        }
+        let eye = self.eye();
+        let d = [p[0] - eye[0], p[1] - eye[1], p[2] - eye[2]];
+        dot3(d, d).sqrt() * (self.fov.to_radians() / 2.0).tan()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum GizmoMode {
+    Off,
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    fn names() -> [&'static str; 4] {
+        ["off", "move", "rotate", "scale"]
+    }
+
+    fn from_name(name: &str) -> GizmoMode {
+        match name {
+            "move" => GizmoMode::Translate,
+            "rotate" => GizmoMode::Rotate,
+            "scale" => GizmoMode::Scale,
+            _ => GizmoMode::Off,
+        }
+    }
+}
+
+// Axis(i) moves, turns or scales along axis i, Plane(i) moves in the plane normal to axis i
+#[derive(Copy, Clone, PartialEq)]
+enum GizmoHandle {
+    Axis(usize),
+    Plane(usize),
+    Center,
+}
+
+const AXIS_COLORS: [[f32; 4]; 3] = [[1.0, 0.2, 0.2, 1.0], [0.2, 1.0, 0.2, 1.0], [0.3, 0.5, 1.0, 1.0]];
+
+fn gizmo_handles(mode: GizmoMode, center: [f32; 3], axes: &[[f32; 3]; 3], size: f32)
+                 -> Vec<(GizmoHandle, Vec<[f32; 3]>)> {
+    let at = |offsets: &[(usize, f32)]| {
+        let mut p = center;
+        for &(axis, t) in offsets {
+            for k in 0..3 {
+                p[k] += axes[axis][k] * t * size;
+            }
+        }
+        p
+    };
+    let mut handles = vec![];
+    match mode {
+        GizmoMode::Off => (),
+        GizmoMode::Translate => for i in 0..3 {
+            let (u, v) = ((i + 1) % 3, (i + 2) % 3);
+            handles.push((GizmoHandle::Axis(i), vec![center, at(&[(i, 1.0)])]));
+            handles.push((GizmoHandle::Plane(i), vec![at(&[(u, 0.3), (v, 0.3)]), at(&[(u, 0.45), (v, 0.3)]),
+                at(&[(u, 0.45), (v, 0.45)]), at(&[(u, 0.3), (v, 0.45)]), at(&[(u, 0.3), (v, 0.3)])]));
+        },
+        GizmoMode::Rotate => for i in 0..3 {
+            let (u, v) = ((i + 1) % 3, (i + 2) % 3);
+            handles.push((GizmoHandle::Axis(i), (0..49).map(|k| {
+                let angle = k as f32 * std::f32::consts::PI / 24.0;
+                at(&[(u, angle.cos()), (v, angle.sin())])
+            }).collect()));
+        },
+        GizmoMode::Scale => {
+            for i in 0..3 {
+                let (u, v) = ((i + 1) % 3, (i + 2) % 3);
+                handles.push((GizmoHandle::Axis(i), vec![center, at(&[(i, 1.0)]), at(&[(i, 1.0), (u, 0.05)]),
+                    at(&[(i, 1.0), (v, 0.05)]), at(&[(i, 1.0)])]));
+            }
+            handles.push((GizmoHandle::Center, vec![at(&[(0, 0.25)]), at(&[(1, 0.25)]), at(&[(2, 0.25)]),
+                at(&[(0, 0.25)])]));
+        },
+    }
+    handles
+}
+
+// the handle whose projected outline passes within a few pixels of (x, y)
+fn pick_gizmo(handles: &[(GizmoHandle, Vec<[f32; 3]>)], view_projection: &[[f32; 4]; 4],
+              x: f32, y: f32, width: f32, height: f32) -> Option<GizmoHandle> {
+    let project = |p: [f32; 3]| {
+        let m = view_projection;
+        let w = m[0][3] * p[0] + m[1][3] * p[1] + m[2][3] * p[2] + m[3][3];
+        let q = transform_point(m, p);
+        [(q[0] / w + 1.0) / 2.0 * width, (1.0 - q[1] / w) / 2.0 * height]
+    };
+    let mut best = None;
+    let mut best_distance = 8.0f32;
+    for &(handle, ref points) in handles {
+        for segment in points.windows(2) {
+            let (a, b) = (project(segment[0]), project(segment[1]));
+            let d = [b[0] - a[0], b[1] - a[1]];
+            let length2 = d[0]*d[0] + d[1]*d[1];
+            let t = if length2 > 0.0 {
+                (((x - a[0]) * d[0] + (y - a[1]) * d[1]) / length2).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            let (px, py) = (a[0] + d[0] * t - x, a[1] + d[1] * t - y);
+            let distance = (px*px + py*py).sqrt();
+            if distance < best_distance {
+                best_distance = distance;
+                best = Some(handle);
+            }
+        }
+    }
+    best
+}
+
+// parameter along the axis line of its closest approach to the ray
+fn closest_on_axis(origin: [f32; 3], dir: [f32; 3], center: [f32; 3], axis: [f32; 3]) -> Option<f32> {
+    let w = [center[0] - origin[0], center[1] - origin[1], center[2] - origin[2]];
+    let (a, b, c) = (dot3(axis, axis), dot3(axis, dir), dot3(dir, dir));
+    let (d, e) = (dot3(axis, w), dot3(dir, w));
+    let denominator = a * c - b * b;
+    if denominator.abs() < 1e-6 {
+        None
+    } else {
+        Some((b * e - c * d) / denominator)
+    }
+}
+
+fn ray_plane(origin: [f32; 3], dir: [f32; 3], point: [f32; 3], normal: [f32; 3]) -> Option<[f32; 3]> {
+    let denominator = dot3(dir, normal);
+    if denominator.abs() < 1e-6 {
+        return None;
+    }
+    let t = dot3([point[0] - origin[0], point[1] - origin[1], point[2] - origin[2]], normal) / denominator;
+    Some([origin[0] + dir[0] * t, origin[1] + dir[1] * t, origin[2] + dir[2] * t])
+}
+
+fn snap_value(value: f32, step: Option<f32>) -> f32 {
+    match step {
+        Some(step) if step > 0.0 => (value / step).round() * step,
+        _ => value,
+    }
+}
+
+struct GizmoDrag {
+    handle: GizmoHandle,
+    mode: GizmoMode,
+    center: [f32; 3],
+    axes: [[f32; 3]; 3],
+    start: [f32; 3],
+    scene_matrix: [[f32; 4]; 4],
+    start_y: f32,
+    translation: [f32; 3],
+    rotation: [f32; 3],
+    scale: [f32; 3],
+}
+
+impl GizmoDrag {
+    // point on the handle's line or plane under the ray, as an axis parameter in [0] for axis handles
+    fn grab(&self, origin: [f32; 3], dir: [f32; 3]) -> Option<[f32; 3]> {
+        match (self.mode, self.handle) {
+            (GizmoMode::Translate, GizmoHandle::Axis(i)) | (GizmoMode::Scale, GizmoHandle::Axis(i)) =>
+                closest_on_axis(origin, dir, self.center, self.axes[i]).map(|t| [t, 0.0, 0.0]),
+            (GizmoMode::Translate, GizmoHandle::Plane(i)) | (GizmoMode::Rotate, GizmoHandle::Axis(i)) =>
+                ray_plane(origin, dir, self.center, self.axes[i]),
+            _ => Some([0.0; 3]),
+        }
+    }
+
+    // new (translation, rotation, scale) for the object; `snap` holds the move, angle and scale steps
+    fn update(&self, origin: [f32; 3], dir: [f32; 3], y: f32, snap: Option<[f32; 3]>)
+              -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let (mut translation, mut rotation, mut scale) = (self.translation, self.rotation, self.scale);
+        let step = |k: usize| snap.map(|snap| snap[k]);
+        let p = match self.grab(origin, dir) {
+            Some(p) => p,
+            None => return (translation, rotation, scale),
+        };
+        match (self.mode, self.handle) {
+            (GizmoMode::Translate, GizmoHandle::Axis(i)) => {
+                translation[i] += snap_value(p[0] - self.start[0], step(0));
+            },
+            (GizmoMode::Translate, GizmoHandle::Plane(i)) => {
+                for k in (0..3).filter(|&k| k != i) {
+                    translation[k] += snap_value(p[k] - self.start[k], step(0));
+                }
+            },
+            (GizmoMode::Rotate, GizmoHandle::Axis(i)) => {
+                let c = self.center;
+                let v0 = [self.start[0] - c[0], self.start[1] - c[1], self.start[2] - c[2]];
+                let v1 = [p[0] - c[0], p[1] - c[1], p[2] - c[2]];
+                let angle = dot3(cross3(v0, v1), self.axes[i]).atan2(dot3(v0, v1)).to_degrees();
+                let mut turn = [0.0f32; 3];
+                turn[i] = snap_value(angle, step(1));
+                rotation = euler_angles(&mat3_mul(&rotation_matrix(turn), &rotation_matrix(self.rotation)));
+            },
+            (GizmoMode::Scale, GizmoHandle::Axis(i)) => if self.start[0].abs() > 1e-6 {
+                scale[i] = snap_value(self.scale[i] * p[0] / self.start[0], step(2)).max(0.01);
+            },
+            (GizmoMode::Scale, GizmoHandle::Center) => {
+                let factor = ((self.start_y - y) / 100.0).exp();
+                for k in 0..3 {
+                    scale[k] = snap_value(self.scale[k] * factor, step(2)).max(0.01);
+                }
+            },
+            _ => (),
+        }
+        (translation, rotation, scale)
+    }
+}
+
+// two unit vectors spanning the plane with the given normal
+fn plane_basis(normal: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+    let helper = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let along = dot3(normal, helper);
+    let u = normalize3([helper[0] - normal[0]*along, helper[1] - normal[1]*along, helper[2] - normal[2]*along]);
+    (u, cross3(normal, u))
+}
+
+// segments where the plane dot(normal, p) = offset cuts the visible objects, in world coordinates
+fn section_segments(objects: &[SceneObject], normal: [f32; 3], offset: f32) -> Vec<[[f32; 3]; 2]> {
+    let mut segments = vec![];
+    for object in objects.iter().filter(|object| object.is_visible) {
+        let m = object.model_matrix();
+        let positions: Vec<_> = object.model.vertices.iter().map(|vertex| transform_point(&m, vertex.position)).collect();
+        for triangle in object.model.indices.chunks(3) {
+            let p = [positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]];
+            let d = [dot3(normal, p[0]) - offset, dot3(normal, p[1]) - offset, dot3(normal, p[2]) - offset];
+            let mut points = vec![];
+            for i in 0..3 {
+                let j = (i + 1) % 3;
+                if (d[i] >= 0.0) != (d[j] >= 0.0) {
+                    let t = d[i] / (d[i] - d[j]);
+                    points.push([p[i][0] + (p[j][0] - p[i][0]) * t, p[i][1] + (p[j][1] - p[i][1]) * t,
+                                 p[i][2] + (p[j][2] - p[i][2]) * t]);
+                }
+            }
+            if points.len() == 2 {
+                segments.push([points[0], points[1]]);
+            }
+        }
+    }
+    segments
+}
+
+// joins segments sharing end points into polylines; closed loops repeat their first point at the end
+fn chain_segments(segments: &[[[f32; 3]; 2]], tolerance: f32) -> Vec<Vec<[f32; 3]>> {
+    use std::collections::HashMap;
+    let key = |p: [f32; 3]| ((p[0] / tolerance).round() as i64, (p[1] / tolerance).round() as i64,
+                             (p[2] / tolerance).round() as i64);
+    let mut ends: HashMap<_, Vec<usize>> = HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        ends.entry(key(segment[0])).or_insert_with(Vec::new).push(i);
+        ends.entry(key(segment[1])).or_insert_with(Vec::new).push(i);
+    }
+    let mut is_used = vec![false; segments.len()];
+    let mut polylines = vec![];
+    for start in 0..segments.len() {
+        if is_used[start] {
+            continue;
+        }
+        is_used[start] = true;
+        let mut polyline = vec![segments[start][0], segments[start][1]];
+        for &is_forward in &[true, false] {
+            loop {
+                let end = if is_forward { *polyline.last().unwrap() } else { polyline[0] };
+                let next = ends.get(&key(end)).and_then(|candidates| {
+                    candidates.iter().cloned().find(|&i| !is_used[i])
+                });
+                let next = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                is_used[next] = true;
+                let segment = segments[next];
+                let point = if key(segment[0]) == key(end) { segment[1] } else { segment[0] };
+                if is_forward {
+                    polyline.push(point);
+                } else {
+                    polyline.insert(0, point);
+                }
+            }
+        }
+        polylines.push(polyline);
+    }
+    polylines
+}
+
+fn write_section_svg<W: std::io::Write>(out: &mut W, polylines: &[Vec<[f32; 2]>]) -> std::io::Result<()> {
+    let mut min = [std::f32::MAX; 2];
+    let mut max = [std::f32::MIN; 2];
+    for p in polylines.iter().flat_map(|polyline| polyline.iter()) {
+        for i in 0..2 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    if min[0] > max[0] {
+        min = [0.0; 2];
+        max = [0.0; 2];
+    }
+    let (width, height) = (max[0] - min[0], max[1] - min[1]);
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}mm\" height=\"{}mm\" viewBox=\"{} {} {} {}\">",
+             width, height, min[0], -max[1], width, height)?;
+    for polyline in polylines {
+        let mut path = String::new();
+        for (i, p) in polyline.iter().enumerate() {
+            path.push_str(&format!("{}{} {} ", if i == 0 { "M" } else { "L" }, p[0], -p[1]));
+        }
+        writeln!(out, "<path d=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>",
+                 path.trim_end(), (width.max(height) / 500.0).max(0.01))?;
+    }
+    writeln!(out, "</svg>")
+}
+
+fn write_section_dxf<W: std::io::Write>(out: &mut W, polylines: &[Vec<[f32; 2]>]) -> std::io::Result<()> {
+    writeln!(out, "0\nSECTION\n2\nENTITIES")?;
+    for polyline in polylines {
+        for segment in polyline.windows(2) {
+            writeln!(out, "0\nLINE\n8\nSECTION\n10\n{}\n20\n{}\n30\n0.0\n11\n{}\n21\n{}\n31\n0.0",
+                     segment[0][0], segment[0][1], segment[1][0], segment[1][1])?;
+        }
+    }
+    writeln!(out, "0\nENDSEC\n0\nEOF")
+}
+
+// writes the section outline in plane coordinates, as DXF for a .dxf path and as SVG otherwise
+fn export_section(path: &std::path::Path, objects: &[SceneObject], normal: [f32; 3], offset: f32)
+                  -> Result<(), String> {
+    let segments = section_segments(objects, normal, offset);
+    if segments.is_empty() {
+        return Err("the plane does not cut any visible object".to_string());
+    }
+    let mut radius = 0.0f32;
+    for segment in &segments {
+        for p in segment {
+            radius = radius.max(dot3(*p, *p).sqrt());
+        }
+    }
+    let (u, v) = plane_basis(normal);
+    let polylines: Vec<Vec<[f32; 2]>> = chain_segments(&segments, 1e-5 * radius.max(1.0)).iter().map(|polyline| {
+        polyline.iter().map(|p| [dot3(*p, u), dot3(*p, v)]).collect()
+    }).collect();
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut out = std::io::BufWriter::new(file);
+    let is_dxf = path.extension().and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase() == "dxf").unwrap_or(false);
+    if is_dxf {
+        write_section_dxf(&mut out, &polylines)
+    } else {
+        write_section_svg(&mut out, &polylines)
+    }.and_then(|_| {
+        use std::io::Write;
+        out.flush()
+    }).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+const MAX_LIGHTS: usize = 4;
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+#[derive(Copy, Clone, PartialEq)]
+enum LightKind {
+    Point,
+    Directional,
+    Spot,
+}
+
+impl LightKind {
+    fn names() -> [&'static str; 3] {
+        ["point", "directional", "spot"]
+    }
+
+    fn from_name(name: &str) -> LightKind {
+        match name {
+            "directional" => LightKind::Directional,
+            "spot" => LightKind::Spot,
+            _ => LightKind::Point,
+        }
+    }
+
+    fn index(&self) -> i32 {
+        match *self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Spot => 2,
+        }
+    }
+}
+
+// position is relative to the scene center in scene radii, direction is where the light shines
+#[derive(Copy, Clone)]
+struct Light {
+    kind: LightKind,
+    color: [f32; 3],
+    intensity: f32,
+    position: [f32; 3],
+    direction: [f32; 3],
+    attenuation: f32,
+    cutoff: f32,
+    is_enabled: bool,
+}
+
+impl Default for Light {
+    fn default() -> Light {
+        Light {
+            kind: LightKind::Point,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            position: [-0.5, 0.5, 0.0],
+            direction: [0.5, -0.5, 0.0],
+            attenuation: 0.0,
+            cutoff: 30.0,
+            is_enabled: true,
+        }
+    }
+}
+
+// adds the lights[i] array and light_count on top of the uniforms built with uniform!
+struct LightUniforms<U> {
+    base: U,
+    lights: Vec<Light>,
+    center: [f32; 3],
+    radius: f32,
+}
+
+impl<U: glium::uniforms::Uniforms> glium::uniforms::Uniforms for LightUniforms<U> {
+    fn visit_values<'a, F: FnMut(&str, glium::uniforms::UniformValue<'a>)>(&'a self, mut output: F) {
+        use glium::uniforms::UniformValue;
+        self.base.visit_values(&mut output);
+        output("light_count", UniformValue::SignedInt(self.lights.len() as i32));
+        output("scene_radius", UniformValue::Float(self.radius));
+        for (i, light) in self.lights.iter().enumerate() {
+            let p = light.position;
+            let color = [light.color[0]*light.intensity, light.color[1]*light.intensity,
+                         light.color[2]*light.intensity];
+            output(&format!("lights[{}].kind", i), UniformValue::SignedInt(light.kind.index()));
+            output(&format!("lights[{}].color", i), UniformValue::Vec3(color));
+            output(&format!("lights[{}].position", i), UniformValue::Vec3([self.center[0] + p[0]*self.radius,
+                self.center[1] + p[1]*self.radius, self.center[2] + p[2]*self.radius]));
+            output(&format!("lights[{}].direction", i), UniformValue::Vec3(normalize3(light.direction)));
+            output(&format!("lights[{}].attenuation", i), UniformValue::Float(light.attenuation));
+            output(&format!("lights[{}].cos_cutoff", i), UniformValue::Float(light.cutoff.to_radians().cos()));
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum MeasureMode {
+    Off,
+    Distance,
+    Angle,
+}
+
+impl MeasureMode {
+    fn names() -> [&'static str; 3] {
+        ["off", "distance", "angle"]
+    }
+
+    fn from_name(name: &str) -> MeasureMode {
+        match name {
+            "distance" => MeasureMode::Distance,
+            "angle" => MeasureMode::Angle,
+            _ => MeasureMode::Off,
+        }
+    }
+
+    fn point_count(&self) -> usize {
+        match *self {
+            MeasureMode::Off => 0,
+            MeasureMode::Distance => 2,
+            MeasureMode::Angle => 3,
+        }
     }
-    return normalize_model;
 }
 
-struct ModelState {
-    model: std::vec::Vec<VertexModel>,
-    is_render : bool,
+// a picked surface point, in world coordinates and in the coordinates of the object it lies on
+#[derive(Copy, Clone)]
+struct MeasurePoint {
+    world: [f32; 3],
+    object: u64,
+    local: [f32; 3],
+    is_scaled: bool,
+}
+
+// `points` are drawn in the scene; `measured` are the same points in the picked object's own
+// coordinates, so a gizmo scale does not change the result, or in scene coordinates when the points
+// lie on different objects, with `is_scaled` set if one of them is scaled
+struct Measurement {
+    points: Vec<[f32; 3]>,
+    measured: Vec<[f32; 3]>,
+    is_scaled: bool,
+}
+
+impl Measurement {
+    fn new(picked: &[MeasurePoint]) -> Measurement {
+        let is_same_object = picked.iter().all(|point| point.object == picked[0].object);
+        Measurement {
+            points: picked.iter().map(|point| point.world).collect(),
+            measured: picked.iter().map(|point| if is_same_object { point.local } else { point.world }).collect(),
+            is_scaled: !is_same_object && picked.iter().any(|point| point.is_scaled),
+        }
+    }
+
+    // distances are bare numbers, the file formats carry no units
+    fn label(&self) -> String {
+        let p = &self.measured;
+        let d = |a: [f32; 3], b: [f32; 3]| [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let scaled = if self.is_scaled { " (scaled)" } else { "" };
+        if p.len() == 2 {
+            let v = d(p[0], p[1]);
+            format!("{:.3}{}", dot3(v, v).sqrt(), scaled)
+        } else {
+            let (u, v) = (d(p[1], p[0]), d(p[1], p[2]));
+            format!("{:.2}\u{b0}{}", dot3(cross3(u, v), cross3(u, v)).sqrt().atan2(dot3(u, v)).to_degrees(), scaled)
+        }
+    }
+
+    fn describe(&self) -> String {
+        let p = &self.measured;
+        if p.len() == 2 {
+            format!("distance {} from ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3}), \
+                     \u{394} ({:.3}, {:.3}, {:.3})", self.label(), p[0][0], p[0][1], p[0][2],
+                    p[1][0], p[1][1], p[1][2], p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2])
+        } else {
+            format!("angle {} at ({:.3}, {:.3}, {:.3})", self.label(), p[1][0], p[1][1], p[1][2])
+        }
+    }
+
+    // where the label goes: the middle of a distance, the vertex of an angle
+    fn anchor(&self) -> [f32; 3] {
+        let p = &self.points;
+        if p.len() == 2 {
+            [(p[0][0] + p[1][0]) / 2.0, (p[0][1] + p[1][1]) / 2.0, (p[0][2] + p[1][2]) / 2.0]
+        } else {
+            p[1]
+        }
+    }
 }
 
 fn main() {
@@ -105,7 +2801,12 @@ fn main() {
 
     let window = Window::new(gtk::WindowType::Toplevel);
     let glarea = GLArea::new();
+    let statusbar = gtk::Statusbar::new();
+    let measure_store = gtk::ListStore::new(&[gtk::Type::String]);
+    let label_layer = gtk::Fixed::new();
+    let measure_labels: Rc<RefCell<Vec<gtk::Label>>> = Rc::new(RefCell::new(vec![]));
     glarea.set_has_depth_buffer(true);
+    glarea.set_has_stencil_buffer(true);
     window.connect_delete_event(|_, _| {
         gtk::main_quit();
         Inhibit(false)
@@ -171,27 +2872,243 @@ fn main() {
     }
 
     implement_vertex!(VertexLight, position);
+
+    #[derive(Copy, Clone)]
+    struct VertexGizmo {
+        position: [f32; 3],
+        color: [f32; 4],
+    }
+
+    implement_vertex!(VertexGizmo, position, color);
     struct State {
         display: Facade,
         light_buffer: glium::VertexBuffer<VertexLight>,
         light_indices: glium::index::NoIndices,
         program_light: glium::program::Program,
-        model_buffer: glium::VertexBuffer<VertexModel>,
-        model_indices: glium::index::NoIndices,
+        object_buffers: std::collections::HashMap<u64, ObjectBuffers>,
+        is_problems: bool,
+        program_gizmo: glium::program::Program,
+        program_shadow: glium::program::Program,
+        shadow_map: glium::texture::DepthTexture2d,
+        is_shadows: bool,
+        shadow_bias: f32,
+        is_ground: bool,
+        gizmo_mode: GizmoMode,
+        gizmo_hover: Option<GizmoHandle>,
+        gizmo_drag: Option<GizmoDrag>,
+        snap: Option<[f32; 3]>,
+        press: Option<(f64, f64)>,
+        picked: Option<(u64, usize)>,
+        measure_mode: MeasureMode,
+        measure_points: Vec<MeasurePoint>,
+        measurements: Vec<Measurement>,
+        label_anchors: Vec<(String, i32, i32)>,
         program_model: glium::program::Program,
+        program_edge: glium::program::Program,
         texture: glium::texture::Texture2d,
-        tx: f32, ty: f32, tz: f32,
-        rx: f32, ry: f32,
+        textures: std::collections::HashMap<TextureSource, glium::texture::Texture2d>,
+        texture_filter: TextureFilter,
+        texture_wrap: TextureWrap,
+        lights: Vec<Light>,
+        light_index: usize,
+        camera: Camera,
+        drag: Option<(u32, f64, f64)>,
+        view_target: Option<(f32, f32)>,
         scale: f32,
         is_draw: bool,
         is_light: bool, is_texture: bool,
-        int: f32, amb: f32, diff: f32, spec: f32,
-        back_color : gdk::RGBA, model_color : gdk::RGBA,
+        amb: f32, diff: f32, spec: f32,
+        back_color : gdk::RGBA,
+        render_mode: RenderMode,
+        line_styles: [(gdk::RGBA, f32); 5],
+        clip: Option<([f32; 3], f32)>,
+        is_cap: bool,
     }
 
     let state: Rc<RefCell<Option<State>>> = Rc::new(RefCell::new(None));
 
-    glarea.connect_realize(clone!(glarea, state; |_widget| {
+    struct ObjectBuffers {
+        revision: u64,
+        vertices: glium::VertexBuffer<VertexModel>,
+        indices: glium::IndexBuffer<u32>,
+        problem_triangles: glium::VertexBuffer<VertexLight>,
+        problem_edges: glium::VertexBuffer<VertexLight>,
+    }
+
+    fn object_buffers(display: &Facade, object: &SceneObject) -> ObjectBuffers {
+        let report = &object.model.report;
+        let triangles: Vec<_> = report.problem_triangles.iter().map(|p| VertexLight { position: *p }).collect();
+        let edges: Vec<_> = report.problem_edges.iter().map(|p| VertexLight { position: *p }).collect();
+        ObjectBuffers {
+            revision: object.revision,
+            vertices: glium::VertexBuffer::new(display, &object.shaded.vertices).unwrap(),
+            indices: glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList,
+                                             &object.shaded.indices).unwrap(),
+            problem_triangles: glium::VertexBuffer::new(display, &triangles).unwrap(),
+            problem_edges: glium::VertexBuffer::new(display, &edges).unwrap(),
+        }
+    }
+
+    struct GizmoView {
+        object: usize,
+        center: [f32; 3],
+        axes: [[f32; 3]; 3],
+        handles: Vec<(GizmoHandle, Vec<[f32; 3]>)>,
+        scene_matrix: [[f32; 4]; 4],
+        view_projection: [[f32; 4]; 4],
+    }
+
+    // the display scale of the whole scene about its center, held fixed while a gizmo drag
+    // changes the bounds
+    fn scene_matrix(state: &State, model_state: &ModelState) -> [[f32; 4]; 4] {
+        if let Some(ref drag) = state.gizmo_drag {
+            return drag.scene_matrix;
+        }
+        let (center, _) = model_state.bounds();
+        let k = state.scale * 2.0;
+        translation_scale([center[0]*(1.0 - k), center[1]*(1.0 - k), center[2]*(1.0 - k)], k)
+    }
+
+    fn gizmo_view(state: &State, model_state: &ModelState, width: f32, height: f32) -> Option<GizmoView> {
+        let index = model_state.selected?;
+        let object = &model_state.objects[index];
+        if state.gizmo_mode == GizmoMode::Off || !object.is_visible {
+            return None;
+        }
+        let sm = scene_matrix(state, model_state);
+        let center = object.pivot();
+        let size = 0.25 * state.camera.half_height_at(transform_point(&sm, center)) / sm[0][0];
+        let axes = if state.gizmo_mode == GizmoMode::Scale {
+            rotation_matrix(object.rotation)
+        } else {
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        };
+        let vm = mat4_array(&state.camera.view_matrix());
+        let pm = state.camera.projection_matrix(width / height);
+        Some(GizmoView {
+            object: index, center: center, axes: axes,
+            handles: gizmo_handles(state.gizmo_mode, center, &axes, size),
+            scene_matrix: sm, view_projection: mat4_mul(&pm, &mat4_mul(&vm, &sm)),
+        })
+    }
+
+    // camera ray brought back from display space into scene space
+    fn scene_ray(state: &State, scene_matrix: &[[f32; 4]; 4], x: f64, y: f64, width: f32, height: f32)
+                 -> ([f32; 3], [f32; 3]) {
+        let (origin, dir) = state.camera.ray(x as f32, y as f32, width, height);
+        let k = scene_matrix[0][0];
+        ([(origin[0] - scene_matrix[3][0]) / k, (origin[1] - scene_matrix[3][1]) / k,
+          (origin[2] - scene_matrix[3][2]) / k], dir)
+    }
+
+    fn sync_labels(label_layer: &gtk::Fixed, labels: &Rc<RefCell<Vec<gtk::Label>>>, anchors: &[(String, i32, i32)]) {
+        let mut labels = labels.borrow_mut();
+        while labels.len() < anchors.len() {
+            let label = gtk::Label::new("");
+            label_layer.put(&label, 0, 0);
+            labels.push(label);
+        }
+        for (i, label) in labels.iter().enumerate() {
+            match anchors.get(i) {
+                Some(&(ref text, x, y)) => {
+                    label.set_markup(&format!("<span background=\"black\" foreground=\"yellow\"> {} </span>", text));
+                    label_layer.move_(label, x, y);
+                    label.show();
+                },
+                None => label.hide(),
+            }
+        }
+    }
+
+    fn upload_texture(display: &Facade, image: image::RgbaImage) -> glium::texture::Texture2d {
+        let image_dimensions = image.dimensions();
+        let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+        glium::texture::Texture2d::with_mipmaps(display, image,
+            glium::texture::MipmapsOption::AutoGeneratedMipmaps).unwrap()
+    }
+
+    fn load_texture(display: &Facade, path: &std::path::Path) -> Result<glium::texture::Texture2d, String> {
+        use std::io::Read;
+        let mut buf = Vec::new();
+        File::open(path).and_then(|mut file| file.read_to_end(&mut buf))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(upload_texture(display, decode_image(&buf, path)?))
+    }
+
+    fn ensure_texture(state: &mut State, source: &TextureSource) -> Result<(), String> {
+        if !state.textures.contains_key(source) {
+            let texture = match *source {
+                TextureSource::File(ref path) => load_texture(&state.display, path)?,
+                TextureSource::Library(name) => upload_texture(&state.display, library_image(name)),
+            };
+            state.textures.insert(source.clone(), texture);
+        }
+        Ok(())
+    }
+
+    fn library_button(thumbnails: &[(&'static str, gdk_pixbuf::Pixbuf)], on_pick: Rc<dyn Fn(&'static str)>)
+                      -> gtk::MenuButton {
+        let library_button = gtk::MenuButton::new();
+        library_button.add(&gtk::Label::new("library"));
+        library_button.set_tooltip_text("built-in textures");
+        let library_popover = gtk::Popover::new(Some(&library_button));
+        let library_grid = gtk::Grid::new();
+        library_grid.set_row_spacing(3);
+        library_grid.set_column_spacing(3);
+        library_grid.set_border_width(5);
+        for (i, &(name, ref pixbuf)) in thumbnails.iter().enumerate() {
+            let thumbnail_box = gtk::Box::new(gtk::Orientation::Vertical, 1);
+            thumbnail_box.add(&gtk::Image::new_from_pixbuf(Some(pixbuf)));
+            thumbnail_box.add(&gtk::Label::new(name));
+            let thumbnail_button = gtk::Button::new();
+            thumbnail_button.add(&thumbnail_box);
+            thumbnail_button.connect_clicked(clone!(on_pick, library_popover; |_thumbnail_button| {
+                on_pick(name);
+                library_popover.hide();
+            }));
+            library_grid.attach(&thumbnail_button, (i % 4) as i32, (i / 4) as i32, 1, 1);
+        }
+        library_grid.show_all();
+        library_popover.add(&library_grid);
+        library_button.set_popover(Some(&library_popover));
+        library_button
+    }
+
+    fn animate_view(state: &Rc<RefCell<Option<State>>>, glarea: &GLArea, rx: f32, ry: f32) {
+        let mut state_ref = state.borrow_mut();
+        let state_ref = state_ref.as_mut().unwrap();
+        let is_running = state_ref.view_target.is_some();
+        state_ref.view_target = Some((rx, ry));
+        if is_running {
+            return;
+        }
+        gtk::timeout_add(16, clone!(state, glarea; || {
+            let mut state = state.borrow_mut();
+            let state = match state.as_mut() {
+                Some(state) => state,
+                None => return glib::Continue(false),
+            };
+            let (rx, ry) = match state.view_target {
+                Some(view_target) => view_target,
+                None => return glib::Continue(false),
+            };
+            if state.camera.step_towards(rx, ry) {
+                state.view_target = None;
+            }
+            glarea.queue_render();
+            glib::Continue(state.view_target.is_some())
+        }));
+    }
+    let model = make_model("union.stl").unwrap();
+    let shaded = shade_model(&model, Shading::AutoSmooth, 30.0);
+    let model_state: std::sync::Arc<std::sync::Mutex<ModelState>> = std::sync::Arc::new(std::sync::Mutex::new(ModelState{
+        objects : vec![], selected : None, next_id : 0, shading : Shading::AutoSmooth, crease_angle : 30.0,
+        generation : 0, transform_revision : 0, is_fit_pending : true, is_render : false, error : None,
+        boolean_result : None, shading_request : 0, is_reshading : false,
+    }));
+    model_state.lock().unwrap().add_object("union".to_string(), model, shaded);
+
+    glarea.connect_realize(clone!(glarea, state, model_state; |_widget| {
             let mut state = state.borrow_mut();
 
             let display = Facade {
@@ -228,26 +3145,24 @@ fn main() {
 						  VertexLight {position: [-0.18, -0.18, 0.18]},
 						  VertexLight {position: [0.18, -0.18, 0.18]}, VertexLight {position: [0.18, -0.18, -0.18]}];
 
-    let model = make_model("union.stl");
 	let light_buffer = glium::VertexBuffer::new(&display, &cube_light).unwrap();
     let light_indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-	let model_buffer = glium::VertexBuffer::new(&display, &model).unwrap();
-    let model_indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
     let vertex_shader_light = r#"
         #version 330
         in vec3 position;
-        uniform mat4 modelMatrix, projectionMatrix;
+        uniform mat4 modelMatrix, viewMatrix, projectionMatrix;
         void main() {
-            gl_Position = projectionMatrix * modelMatrix * vec4(position, 1.0);
+            gl_Position = projectionMatrix * viewMatrix * modelMatrix * vec4(position, 1.0);
         }
     "#;
 
     let fragment_shader_light = r#"
         #version 330
+        uniform vec3 light_color;
         out vec4 color;
         void main() {
-            color = vec4(1.0, 1.0, 1.0, 1.0);
+            color = vec4(light_color, 1.0);
         }
     "#;
 
@@ -256,15 +3171,25 @@ fn main() {
         in vec3 position;
 		in vec2 tex_coords;
 		in vec3 normal;
+		in vec4 color;
+		in vec4 tangent;
 		out vec2 v_tex_coords;
 		out vec3 v_normal;
 		out vec3 v_position;
-        uniform mat4 modelMatrix, projectionMatrix;
+		out vec4 v_color;
+		out vec3 v_local_position;
+		out vec3 v_local_normal;
+		out vec4 v_tangent;
+        uniform mat4 modelMatrix, viewMatrix, projectionMatrix;
         void main() {
 			v_tex_coords = tex_coords;
+			v_tangent = vec4(mat3(modelMatrix)*tangent.xyz, tangent.w);
+			v_local_position = position;
+			v_local_normal = normal;
+			v_color = color;
 			v_normal = normalize(mat3(transpose(inverse(modelMatrix)))*normal);
 			v_position = vec3(modelMatrix*vec4(position, 1.0));
-            gl_Position = projectionMatrix * modelMatrix * vec4(position, 1.0);
+            gl_Position = projectionMatrix * viewMatrix * modelMatrix * vec4(position, 1.0);
         }
     "#;
 
@@ -273,157 +3198,995 @@ fn main() {
 		in vec2 v_tex_coords;
 		in vec3 v_normal;
 		in vec3 v_position;
+		in vec4 v_color;
+		in vec3 v_local_position;
+		in vec3 v_local_normal;
+		in vec4 v_tangent;
         out vec4 color;
         uniform sampler2D tex;
-		uniform vec3 LightPosition;
-		uniform vec3 LightIntensity;
+		uniform int projection;
+		uniform vec2 uv_scale;
+		uniform vec2 uv_offset;
+		uniform float uv_rotation;
+		uniform vec3 bbox_min;
+		uniform vec3 bbox_max;
+		uniform sampler2D detail_map;
+		uniform bool is_detail;
+		uniform bool is_bump;
+		uniform float detail_strength;
+		struct Light {
+			int kind;
+			vec3 color;
+			vec3 position;
+			vec3 direction;
+			float attenuation;
+			float cos_cutoff;
+		};
+		uniform Light lights[4];
+		uniform int light_count;
+		uniform float scene_radius;
+		uniform sampler2D shadow_map;
+		uniform mat4 lightSpaceMatrix;
+		uniform bool is_shadow;
+		uniform float shadow_bias;
 		uniform vec3 MaterialKa;
 		uniform vec3 MaterialKd;
 		uniform float MaterialKs;
 		uniform bool is_light;
 		uniform bool is_texture;
+		uniform bool is_vertex_color;
+		uniform bool is_flat;
 		uniform vec4 model_color;
+		uniform vec3 viewPos;
+		uniform vec4 clip_plane;
+		uniform bool is_clip;
 		out vec4 FragColor;
+		float shadowFactor(vec3 pos, vec3 norm, vec3 lightDir) {
+			vec4 p = lightSpaceMatrix*vec4(pos, 1.0);
+			vec3 c = p.xyz/p.w*0.5 + 0.5;
+			if(c.z > 1.0 || c.x < 0.0 || c.x > 1.0 || c.y < 0.0 || c.y > 1.0) {
+				return 1.0;
+			}
+			float bias = shadow_bias*max(1.0 - dot(norm, lightDir), 0.1);
+			vec2 texel = 1.0/vec2(textureSize(shadow_map, 0));
+			float lit = 0.0;
+			for(int x = -2; x <= 2; x++) {
+				for(int y = -2; y <= 2; y++) {
+					float depth = texture(shadow_map, c.xy + vec2(x, y)*texel).r;
+					lit += c.z - bias > depth ? 0.0 : 1.0;
+				}
+			}
+			return lit/25.0;
+		}
+		vec2 rotateScaleUv(vec2 uv) {
+			float c = cos(uv_rotation);
+			float s = sin(uv_rotation);
+			return vec2(c*uv.x - s*uv.y, s*uv.x + c*uv.y)*uv_scale;
+		}
+		vec2 transformUv(vec2 uv) {
+			return rotateScaleUv(uv - 0.5) + 0.5 + uv_offset;
+		}
+		// texture coordinates of a projection before the UV transform; box is sampled per axis as 1, 2 and 3
+		vec2 baseUv(int mode) {
+			vec3 size = bbox_max - bbox_min;
+			vec3 p = (v_local_position - bbox_min)/max(max(max(size.x, size.y), size.z), 1e-6);
+			vec3 c = v_local_position - (bbox_min + bbox_max)*0.5;
+			float angle = atan(c.z, c.x)/6.2831853 + 0.5;
+			if(mode == 1) {
+				return p.zy;
+			} else if(mode == 2) {
+				return p.xz;
+			} else if(mode == 3) {
+				return p.xy;
+			} else if(mode == 5) {
+				return vec2(angle, p.y);
+			} else if(mode == 6) {
+				return vec2(angle, asin(clamp(c.y/max(length(c), 1e-6), -1.0, 1.0))/3.1415927 + 0.5);
+			}
+			return v_tex_coords;
+		}
+		vec3 boxWeights() {
+			vec3 n = is_flat ? cross(dFdx(v_local_position), dFdy(v_local_position)) : v_local_normal;
+			vec3 w = pow(abs(normalize(n)), vec3(4.0));
+			return w/(w.x + w.y + w.z);
+		}
+		// the angle coordinate wraps around, so its screen derivatives are taken modulo 1
+		void uvGradients(vec2 uv, out vec2 dx, out vec2 dy) {
+			dx = dFdx(uv);
+			dy = dFdy(uv);
+			dx.x -= round(dx.x);
+			dy.x -= round(dy.x);
+		}
+		vec4 sampleUv(sampler2D map, vec2 uv, vec2 offset) {
+			vec2 dx, dy;
+			uvGradients(uv, dx, dy);
+			return textureGrad(map, transformUv(uv) + offset, rotateScaleUv(dx), rotateScaleUv(dy));
+		}
+		vec4 projectedTexture() {
+			if(projection == 4) {
+				vec3 w = boxWeights();
+				return sampleUv(tex, baseUv(1), vec2(0.0))*w.x + sampleUv(tex, baseUv(2), vec2(0.0))*w.y
+					+ sampleUv(tex, baseUv(3), vec2(0.0))*w.z;
+			}
+			return sampleUv(tex, baseUv(projection), vec2(0.0));
+		}
+		// tangent-space normal from the detail map, expressed along the untransformed u and v
+		vec3 detailSample(vec2 uv) {
+			vec3 m;
+			if(is_bump) {
+				vec2 texel = 1.0/vec2(textureSize(detail_map, 0));
+				float h = sampleUv(detail_map, uv, vec2(0.0)).r;
+				float hu = sampleUv(detail_map, uv, vec2(texel.x, 0.0)).r;
+				float hv = sampleUv(detail_map, uv, vec2(0.0, texel.y)).r;
+				m = vec3((h - hu)*detail_strength*4.0, (h - hv)*detail_strength*4.0, 1.0);
+			} else {
+				m = sampleUv(detail_map, uv, vec2(0.0)).xyz*2.0 - 1.0;
+				m.xy *= detail_strength;
+			}
+			float c = cos(uv_rotation);
+			float s = sin(uv_rotation);
+			m.xy = vec2(c*m.x + s*m.y, -s*m.x + c*m.y)*sign(uv_scale);
+			return m;
+		}
+		// tangent frame from the screen derivatives of the position and of uv
+		vec3 perturbByDerivatives(vec3 n, vec2 uv) {
+			vec2 duvdx, duvdy;
+			uvGradients(uv, duvdx, duvdy);
+			vec3 dp1perp = cross(n, dFdx(v_position));
+			vec3 dp2perp = cross(dFdy(v_position), n);
+			vec3 t = dp2perp*duvdx.x + dp1perp*duvdy.x;
+			vec3 b = dp2perp*duvdx.y + dp1perp*duvdy.y;
+			float scale = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-20));
+			vec3 m = detailSample(uv);
+			return m.x*t*scale + m.y*b*scale + m.z*n;
+		}
+		vec3 detailNormal(vec3 n) {
+			if(projection == 0) {
+				vec3 t = normalize(v_tangent.xyz - n*dot(n, v_tangent.xyz));
+				vec3 b = cross(n, t)*v_tangent.w;
+				vec3 m = detailSample(v_tex_coords);
+				return normalize(m.x*t + m.y*b + m.z*n);
+			} else if(projection == 4) {
+				vec3 w = boxWeights();
+				return normalize(perturbByDerivatives(n, baseUv(1))*w.x + perturbByDerivatives(n, baseUv(2))*w.y
+					+ perturbByDerivatives(n, baseUv(3))*w.z);
+			}
+			return normalize(perturbByDerivatives(n, baseUv(projection)));
+		}
 		void phongModel(vec3 pos, vec3 norm, out vec3 ambAndDiffspec) {
-			vec3 ambient = LightIntensity*MaterialKa;
-			vec3 lightDir = normalize(LightPosition - v_position);
-			float diff = max(dot(v_normal, lightDir), 0.0);
-			vec3 diffuse = LightIntensity*(diff * MaterialKd);
-			vec3 viewPos = vec3(0.0, 0.0, 2.0);
 			vec3 viewDir = normalize(viewPos - pos);
-			vec3 r = reflect(-lightDir, norm);
-			vec3 specular = vec3(pow(max(dot(r,viewDir), 0.0), 32)*MaterialKs*diff);
-			ambAndDiffspec = ambient  + diffuse + specular;
+			ambAndDiffspec = vec3(0.0);
+			for(int i = 0; i < light_count; i++) {
+				vec3 lightDir = -lights[i].direction;
+				float factor = 1.0;
+				if(lights[i].kind != 1) {
+					vec3 toLight = lights[i].position - pos;
+					float d = length(toLight) / scene_radius;
+					lightDir = normalize(toLight);
+					factor = 1.0 / (1.0 + lights[i].attenuation*d*d);
+					if(lights[i].kind == 2) {
+						float c = dot(-lightDir, lights[i].direction);
+						factor *= smoothstep(lights[i].cos_cutoff, mix(lights[i].cos_cutoff, 1.0, 0.2), c);
+					}
+				}
+				if(i == 0 && is_shadow) {
+					factor *= shadowFactor(pos, norm, lightDir);
+				}
+				vec3 ambient = lights[i].color*MaterialKa;
+				float diff = max(dot(norm, lightDir), 0.0);
+				vec3 diffuse = lights[i].color*(diff * MaterialKd);
+				vec3 r = reflect(-lightDir, norm);
+				vec3 specular = lights[i].color*pow(max(dot(r,viewDir), 0.0), 32)*MaterialKs*diff;
+				ambAndDiffspec += ambient + factor*(diffuse + specular);
+			}
 		}
 		void main() {
+			if(is_clip && dot(clip_plane.xyz, v_position) > clip_plane.w) {
+				discard;
+			}
 			vec3 ambAndDiffspec;
-			vec4 texColor = texture(tex, v_tex_coords);
-			phongModel(v_position, v_normal, ambAndDiffspec);
+			vec4 texColor = projectedTexture();
+			vec3 normal = is_flat ? normalize(cross(dFdx(v_position), dFdy(v_position))) : normalize(v_normal);
+			if(is_detail) {
+				normal = detailNormal(normal);
+			}
+			phongModel(v_position, normal, ambAndDiffspec);
+			vec4 baseColor = is_vertex_color ? v_color : model_color;
 			if(is_light) {
-				FragColor = vec4(ambAndDiffspec, 1.0) * model_color;
+				FragColor = vec4(ambAndDiffspec, 1.0) * baseColor;
 			} else {
-				FragColor = model_color;
+				FragColor = baseColor;
 			}
 			if(is_texture) {
 				FragColor *= texColor;
 			}
+			// alpha is a cutout, surfaces are drawn unsorted with depth writes
+			if(FragColor.a < 0.5) {
+				discard;
+			}
+			FragColor.a = 1.0;
 		}
     "#;
 
     let program_light = glium::Program::from_source(&display, vertex_shader_light, fragment_shader_light, None).unwrap();
 	let program_model = glium::Program::from_source(&display, vertex_shader_model, fragment_shader_model, None).unwrap();
-    let image = image::load(
-        Cursor::new(&include_bytes!("t2.jpg")[..]),image::JPEG).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let texture = glium::texture::Texture2d::new(&display, image).unwrap();
-
-    let tx = 0.0f32; let ty = 0.0f32; let tz = 0.0f32;
-    let rx = 30.0f32; let ry = 45.0f32;
+
+    let vertex_shader_edge = r#"
+        #version 330
+        in vec3 position;
+        uniform mat4 modelMatrix, viewMatrix, projectionMatrix;
+        out vec3 v_position;
+        void main() {
+            v_position = vec3(modelMatrix*vec4(position, 1.0));
+            gl_Position = projectionMatrix * viewMatrix * modelMatrix * vec4(position, 1.0);
+        }
+    "#;
+
+    let fragment_shader_edge = r#"
+        #version 330
+        in vec3 v_position;
+        uniform vec4 line_color;
+        uniform vec4 clip_plane;
+        uniform bool is_clip;
+        out vec4 color;
+        void main() {
+            if(is_clip && dot(clip_plane.xyz, v_position) > clip_plane.w) {
+                discard;
+            }
+            color = line_color;
+        }
+    "#;
+    let program_edge = glium::Program::from_source(&display, vertex_shader_edge, fragment_shader_edge, None).unwrap();
+
+    let vertex_shader_gizmo = r#"
+        #version 330
+        in vec3 position;
+        in vec4 color;
+        out vec4 v_color;
+        uniform mat4 modelMatrix, viewMatrix, projectionMatrix;
+        void main() {
+            v_color = color;
+            gl_Position = projectionMatrix * viewMatrix * modelMatrix * vec4(position, 1.0);
+        }
+    "#;
+
+    let fragment_shader_gizmo = r#"
+        #version 330
+        in vec4 v_color;
+        out vec4 color;
+        void main() {
+            color = v_color;
+        }
+    "#;
+    let program_gizmo = glium::Program::from_source(&display, vertex_shader_gizmo, fragment_shader_gizmo, None).unwrap();
+
+    let vertex_shader_shadow = r#"
+        #version 330
+        in vec3 position;
+        out vec3 v_position;
+        uniform mat4 modelMatrix, lightSpaceMatrix;
+        void main() {
+            v_position = vec3(modelMatrix*vec4(position, 1.0));
+            gl_Position = lightSpaceMatrix * modelMatrix * vec4(position, 1.0);
+        }
+    "#;
+
+    let fragment_shader_shadow = r#"
+        #version 330
+        in vec3 v_position;
+        uniform vec4 clip_plane;
+        uniform bool is_clip;
+        void main() {
+            if(is_clip && dot(clip_plane.xyz, v_position) > clip_plane.w) {
+                discard;
+            }
+        }
+    "#;
+    let program_shadow = glium::Program::from_source(&display, vertex_shader_shadow, fragment_shader_shadow, None).unwrap();
+    let shadow_map = glium::texture::DepthTexture2d::empty(&display, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE).unwrap();
+    let image = decode_image(&include_bytes!("t2.jpg")[..], std::path::Path::new("t2.jpg")).unwrap();
+    let texture = upload_texture(&display, image);
+
+    let camera = Camera { target: [0.0, 0.0, 0.0], distance: 2.0, rx: 30.0, ry: 45.0, fov: 45.0, is_ortho: false };
     let scale = 0.5f32;
     let is_draw = true;
     let is_light = true;
     let is_texture = true;
-    let int = 1.0f32; let amb = 0.5f32; let diff = 1.0f32; let spec = 0.8f32;
+    let amb = 0.5f32; let diff = 1.0f32; let spec = 0.8f32;
     let back_color = gdk::RGBA{red : 0.0, green : 0.0, blue : 0.0, alpha : 1.0};
-    let model_color = gdk::RGBA{red : 1.0, green : 1.0, blue : 1.0, alpha : 1.0};
+    let white = gdk::RGBA{red : 1.0, green : 1.0, blue : 1.0, alpha : 1.0};
+    let black = gdk::RGBA{red : 0.0, green : 0.0, blue : 0.0, alpha : 1.0};
+    let line_styles = [(white, 1.0), (white, 1.0), (black, 1.0), (white, 1.0), (white, 3.0)];
 
     *state = Some(State {
         display: display,
         light_buffer: light_buffer,
         light_indices: light_indices,
         program_light: program_light,
-        model_buffer: model_buffer,
-        model_indices: model_indices,
+        object_buffers: std::collections::HashMap::new(),
+        is_problems: true,
+        program_gizmo: program_gizmo,
+        program_shadow: program_shadow,
+        shadow_map: shadow_map,
+        is_shadows: false,
+        shadow_bias: 0.002,
+        is_ground: false,
+        gizmo_mode: GizmoMode::Off,
+        gizmo_hover: None,
+        gizmo_drag: None,
+        snap: None,
+        press: None,
+        picked: None,
+        measure_mode: MeasureMode::Off,
+        measure_points: vec![],
+        measurements: vec![],
+        label_anchors: vec![],
         program_model: program_model,
+        program_edge: program_edge,
         texture : texture,
-        tx : tx, ty : ty, tz : tz,
-        rx : rx, ry : ry, scale : scale,
+        textures : std::collections::HashMap::new(),
+        texture_filter : TextureFilter::Trilinear,
+        texture_wrap : TextureWrap::Repeat,
+        lights : vec![Light::default()], light_index : 0,
+        camera : camera, drag : None, view_target : None, scale : scale,
         is_draw : is_draw,
         is_light : is_light, is_texture : is_texture,
-        int : int, amb : amb, diff : diff, spec : spec,
-        back_color : back_color, model_color : model_color,
+        amb : amb, diff : diff, spec : spec,
+        back_color : back_color,
+        render_mode : RenderMode::Shaded, line_styles : line_styles,
+        clip : None, is_cap : true,
          });
     }));
-    let model = make_model("union.stl");
-    let model_state: std::sync::Arc<std::sync::Mutex<ModelState>> = std::sync::Arc::new(std::sync::Mutex::new(ModelState{
-        model : model, is_render : false,
-    }));
 
     glarea.connect_unrealize(clone!(state; |_widget| {
             let mut state = state.borrow_mut();
             *state = None;
         }));
 
-    glarea.connect_render(clone!(state, model_state; |_glarea, _glctx| {
+    glarea.connect_render(clone!(state, model_state, label_layer, measure_labels; |glarea, _glctx| {
             let mut state = state.borrow_mut();
             let state = state.as_mut().unwrap();
-            state.model_buffer = glium::VertexBuffer::new(&state.display, &model_state.lock().unwrap().model).unwrap();
-            let int = [state.int, state.int, state.int];
+            let mut model_state = model_state.lock().unwrap();
+            {
+                let objects = &model_state.objects;
+                state.object_buffers.retain(|id, _| objects.iter().any(|object| object.id == *id));
+            }
+            for object in &model_state.objects {
+                let is_stale = match state.object_buffers.get(&object.id) {
+                    Some(buffers) => buffers.revision != object.revision,
+                    None => true,
+                };
+                if is_stale {
+                    let buffers = object_buffers(&state.display, object);
+                    state.object_buffers.insert(object.id, buffers);
+                }
+            }
+            let (center, radius) = model_state.bounds();
+            if model_state.is_fit_pending {
+                state.camera.fit(center, radius);
+                model_state.is_fit_pending = false;
+            }
+            let is_flat = model_state.shading == Shading::Flat;
             let amb = [state.amb, state.amb, state.amb];
             let diff = [state.diff, state.diff, state.diff];
             let spec = state.spec;
             let back = state.back_color;
-            let color = state.model_color;
             let mut target = state.display.draw();
             target.clear_color_and_depth((back.red as f32,
                 back.green as f32 , back.blue as f32, back.alpha as f32), 1.0);
-            let lm = glm::ext::look_at(glm::vec3(0.0, 0.0, 2.0), glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0));
-            let tm_light0 = glm::ext::translate(&lm, glm::vec3(state.tx-0.5, state.ty+0.5, state.tz));
-            let tm_light = glm::ext::scale(&tm_light0, glm::vec3(0.25, 0.25, 0.25));
-            let tm_light = tm_light.as_array();
-            let rmx = glm::ext::rotate(&lm, glm::radians(state.rx), glm::vec3(1.0, 0.0, 0.0));
-            let rmy = glm::ext::rotate(&rmx, glm::radians(state.ry), glm::vec3(0.0, 1.0, 0.0));
-            let sm = glm::ext::scale(&rmy, glm::vec3(state.scale, state.scale, state.scale));
-            let sm = sm.as_array();
             let (w, h) = target.get_dimensions();
-            let pmv  = glm::ext::perspective_rh(glm::radians(45.0f32),
-                w as f32 / h as f32, 0.1f32, 100.0f32);
-            let pmv = pmv.as_array();
-            let pm = [
-                *pmv[0].as_array(), *pmv[1].as_array(), *pmv[2].as_array(), *pmv[3].as_array(),
-            ];
-            let uniforms_light = uniform! {
-                modelMatrix : [
-                    *tm_light[0].as_array(), *tm_light[1].as_array(),
-                    *tm_light[2].as_array(), *tm_light[3].as_array(),
-                ],
-                projectionMatrix: pm,
-            };
-
-            let uniforms_model = uniform! {
-                modelMatrix : [
-                    *sm[0].as_array(), *sm[1].as_array(), *sm[2].as_array(), *sm[3].as_array(),
-                ],
-                projectionMatrix: pm,
-                tex: glium::uniforms::Sampler::wrap_function(glium::uniforms::Sampler::new
-                    (&state.texture),glium::uniforms::SamplerWrapFunction::Repeat),
-                LightIntensity: int,
-                LightPosition: [state.tx-0.5, state.ty+0.5, 0.0f32],
-                MaterialKa: amb,
-                MaterialKd: diff,
-                MaterialKs: spec,
-                is_light: state.is_light,
-                is_texture: state.is_texture,
-                model_color: [color.red as f32, color.green as f32, color.blue as f32, color.alpha as f32],
+            let vm = mat4_array(&state.camera.view_matrix());
+            let pm = state.camera.projection_matrix(w as f32 / h as f32);
+            let eye = state.camera.eye();
+            let lights: Vec<_> = state.lights.iter().filter(|light| light.is_enabled).take(MAX_LIGHTS).cloned().collect();
+            let sm = scene_matrix(state, &model_state);
+            let (clip_normal, clip_offset) = state.clip.unwrap_or(([0.0, 0.0, 1.0], 0.0));
+            let clip_plane = [clip_normal[0], clip_normal[1], clip_normal[2],
+                sm[0][0]*clip_offset + dot3(clip_normal, [sm[3][0], sm[3][1], sm[3][2]])];
+            let is_clip = state.clip.is_some();
+            let extent = 2.5*radius*sm[0][0];
+            let is_shadow = state.is_shadows && state.is_light && !lights.is_empty();
+            let light_space = match lights.first() {
+                Some(light) => light_space_matrix(light, [center[0] + light.position[0]*radius,
+                    center[1] + light.position[1]*radius, center[2] + light.position[2]*radius], center, extent),
+                None => translation_scale([0.0, 0.0, 0.0], 1.0),
             };
+            if is_shadow {
+                let mut shadow_target = glium::framebuffer::SimpleFrameBuffer::depth_only(&state.display,
+                    &state.shadow_map).unwrap();
+                shadow_target.clear_depth(1.0);
+                let shadow_params = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::DepthTest::IfLess,
+                        write: true,
+                        .. Default::default()
+                    },
+                    .. Default::default()
+                };
+                for object in model_state.objects.iter().filter(|object| object.is_visible) {
+                    let buffers = &state.object_buffers[&object.id];
+                    let uniforms_shadow = uniform! {
+                        modelMatrix : mat4_mul(&sm, &object.model_matrix()),
+                        lightSpaceMatrix: light_space,
+                        clip_plane: clip_plane,
+                        is_clip: is_clip,
+                    };
+                    shadow_target.draw(&buffers.vertices, &buffers.indices, &state.program_shadow,
+                        &uniforms_shadow, &shadow_params).unwrap();
+                }
+            }
+            let shadow_sampler = glium::uniforms::Sampler::new(&state.shadow_map)
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+                .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
             let params = glium::DrawParameters {
                 viewport: Some(glium::Rect {
                     left : 0, bottom : 0,  width : w, height : h
                 }),
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLess,
+                    write: true,
+                    .. Default::default()
+                },
                 .. Default::default()
             };
+            // filled surfaces are pushed back so edges and overlays drawn on them at their true depth win
+            let fill_params = glium::DrawParameters {
+                polygon_offset: glium::draw_parameters::PolygonOffset {
+                    factor: 1.0,
+                    units: 1.0,
+                    fill: true,
+                    .. Default::default()
+                },
+                .. params.clone()
+            };
+            let (line_color, line_width) = state.line_styles[state.render_mode.index()];
+            let line_params = glium::DrawParameters {
+                polygon_mode: if state.render_mode == RenderMode::Points {
+                    glium::PolygonMode::Point
+                } else {
+                    glium::PolygonMode::Line
+                },
+                line_width: Some(line_width),
+                point_size: Some(line_width),
+                depth: glium::Depth {
+                    test: if state.render_mode == RenderMode::Wireframe {
+                        glium::DepthTest::Overwrite
+                    } else {
+                        glium::DepthTest::IfLessOrEqual
+                    },
+                    write: false,
+                    .. Default::default()
+                },
+                .. params.clone()
+            };
+            let problem_params = glium::DrawParameters {
+                line_width: Some(3.0),
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLessOrEqual,
+                    write: false,
+                    .. Default::default()
+                },
+                .. params.clone()
+            };
             if state.is_draw {
                 if state.is_light {
-                    target.draw(&state.light_buffer, &state.light_indices, &state.program_light,
-                        &uniforms_light,&params).unwrap();
+                    let mut light_lines = vec![];
+                    for light in &lights {
+                        let p = [center[0] + light.position[0]*radius, center[1] + light.position[1]*radius,
+                            center[2] + light.position[2]*radius];
+                        let uniforms_light = uniform! {
+                            modelMatrix : translation_scale(p, 0.25*radius),
+                            viewMatrix: vm,
+                            projectionMatrix: pm,
+                            light_color: light.color,
+                        };
+                        target.draw(&state.light_buffer, &state.light_indices, &state.program_light,
+                            &uniforms_light,&params).unwrap();
+                        if light.kind != LightKind::Point {
+                            let d = normalize3(light.direction);
+                            let color = [light.color[0], light.color[1], light.color[2], 1.0];
+                            light_lines.push(VertexGizmo { position: p, color: color });
+                            light_lines.push(VertexGizmo { position: [p[0] + d[0]*0.5*radius,
+                                p[1] + d[1]*0.5*radius, p[2] + d[2]*0.5*radius], color: color });
+                        }
+                    }
+                    if !light_lines.is_empty() {
+                        let light_lines = glium::VertexBuffer::new(&state.display, &light_lines).unwrap();
+                        let uniforms_lines = uniform! {
+                            modelMatrix : translation_scale([0.0, 0.0, 0.0], 1.0),
+                            viewMatrix: vm,
+                            projectionMatrix: pm,
+                        };
+                        target.draw(&light_lines, &glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                            &state.program_gizmo, &uniforms_lines, &params).unwrap();
+                    }
+                }
+                if state.is_ground {
+                    let ground_y = model_state.objects.iter().filter(|object| object.is_visible)
+                        .map(|object| object.world_bounds().0[1]).fold(std::f32::MAX, f32::min);
+                    let ground_y = if ground_y == std::f32::MAX { center[1] } else { ground_y };
+                    let y = sm[1][1]*ground_y + sm[3][1] - 0.01*extent;
+                    let size = 0.6*extent;
+                    let corner = |dx: f32, dz: f32| VertexModel {
+                        position: [center[0] + dx*size, y, center[2] + dz*size],
+                        tex_coords: [0.0, 0.0],
+                        normal: [0.0, 1.0, 0.0],
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        tangent: [1.0, 0.0, 0.0, 1.0],
+                    };
+                    let ground = [corner(-1.0, -1.0), corner(-1.0, 1.0), corner(1.0, 1.0),
+                        corner(-1.0, -1.0), corner(1.0, 1.0), corner(1.0, -1.0)];
+                    let ground_buffer = glium::VertexBuffer::new(&state.display, &ground).unwrap();
+                    let uniforms_ground = LightUniforms { base: uniform! {
+                        modelMatrix : translation_scale([0.0, 0.0, 0.0], 1.0),
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                        viewPos: eye,
+                        tex: &state.texture,
+                        MaterialKa: amb,
+                        MaterialKd: diff,
+                        MaterialKs: spec,
+                        is_light: state.is_light,
+                        is_texture: false,
+                        is_vertex_color: false,
+                        is_flat: false,
+                        model_color: [0.6f32, 0.6, 0.6, 1.0],
+                        is_clip: false,
+                        shadow_map: shadow_sampler,
+                        lightSpaceMatrix: light_space,
+                        is_shadow: is_shadow,
+                        shadow_bias: state.shadow_bias,
+                    }, lights: lights.clone(), center: center, radius: radius };
+                    target.draw(&ground_buffer, &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                        &state.program_model, &uniforms_ground, &params).unwrap();
+                }
+                for object in model_state.objects.iter().filter(|object| object.is_visible) {
+                    let buffers = &state.object_buffers[&object.id];
+                    let om = mat4_mul(&sm, &object.model_matrix());
+                    let texture = match object.texture {
+                        Some(ref source) => state.textures.get(source).unwrap_or(&state.texture),
+                        None => &state.texture,
+                    };
+                    let detail_map = object.detail_map.as_ref().and_then(|source| state.textures.get(source));
+                    let is_detail = object.detail_kind != DetailKind::Off && detail_map.is_some();
+                    let uniforms_model = LightUniforms { base: uniform! {
+                        modelMatrix : om,
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                        viewPos: eye,
+                        tex: glium::uniforms::Sampler::new(texture)
+                            .wrap_function(state.texture_wrap.function())
+                            .minify_filter(state.texture_filter.filters().0)
+                            .magnify_filter(state.texture_filter.filters().1),
+                        MaterialKa: amb,
+                        MaterialKd: diff,
+                        MaterialKs: spec,
+                        is_light: state.is_light,
+                        is_texture: state.is_texture,
+                        is_vertex_color: object.model.has_colors,
+                        is_flat: is_flat,
+                        model_color: object.color,
+                        projection: object.mapping.projection.index(),
+                        uv_scale: object.mapping.scale,
+                        uv_offset: object.mapping.offset,
+                        uv_rotation: object.mapping.rotation.to_radians(),
+                        bbox_min: object.model.bbox_min,
+                        bbox_max: object.model.bbox_max,
+                        detail_map: glium::uniforms::Sampler::new(detail_map.unwrap_or(&state.texture))
+                            .wrap_function(state.texture_wrap.function())
+                            .minify_filter(state.texture_filter.filters().0)
+                            .magnify_filter(state.texture_filter.filters().1),
+                        is_detail: is_detail,
+                        is_bump: object.detail_kind == DetailKind::BumpMap,
+                        detail_strength: object.detail_strength,
+                        clip_plane: clip_plane,
+                        is_clip: is_clip,
+                        shadow_map: shadow_sampler,
+                        lightSpaceMatrix: light_space,
+                        is_shadow: is_shadow,
+                        shadow_bias: state.shadow_bias,
+                    }, lights: lights.clone(), center: center, radius: radius };
+                    let uniforms_edge = uniform! {
+                        modelMatrix : om,
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                        line_color: [line_color.red as f32, line_color.green as f32,
+                            line_color.blue as f32, line_color.alpha as f32],
+                        clip_plane: clip_plane,
+                        is_clip: is_clip,
+                    };
+                    let uniforms_hidden = uniform! {
+                        modelMatrix : om,
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                        line_color: [back.red as f32, back.green as f32, back.blue as f32, back.alpha as f32],
+                        clip_plane: clip_plane,
+                        is_clip: is_clip,
+                    };
+                    match state.render_mode {
+                        RenderMode::Shaded | RenderMode::ShadedEdges => {
+                            target.draw(&buffers.vertices, &buffers.indices, &state.program_model,
+                                &uniforms_model,&fill_params).unwrap();
+                        },
+                        RenderMode::HiddenLine => {
+                            target.draw(&buffers.vertices, &buffers.indices, &state.program_edge,
+                                &uniforms_hidden,&fill_params).unwrap();
+                        },
+                        RenderMode::Wireframe | RenderMode::Points => (),
+                    }
+                    if is_clip && state.is_cap && state.render_mode != RenderMode::Wireframe
+                            && state.render_mode != RenderMode::Points {
+                        // the stencil flips for every kept surface along the view ray, so it is set
+                        // exactly where the plane lies inside the solid
+                        let normal = [clip_plane[0], clip_plane[1], clip_plane[2]];
+                        let (u, v) = plane_basis(normal);
+                        let (min, max) = object.world_bounds();
+                        let mut center = [0.0; 3];
+                        let mut size = 0.0;
+                        for i in 0..3 {
+                            center[i] = sm[0][0]*(min[i] + max[i])/2.0 + sm[3][i];
+                            size += (sm[0][0]*(max[i] - min[i])).powi(2);
+                        }
+                        let size = 0.75*size.sqrt();
+                        let distance = dot3(normal, center) - clip_plane[3];
+                        let center = [center[0] - normal[0]*distance, center[1] - normal[1]*distance,
+                            center[2] - normal[2]*distance];
+                        let corner = |a: f32, b: f32| VertexLight { position: [
+                            center[0] + (u[0]*a + v[0]*b)*size, center[1] + (u[1]*a + v[1]*b)*size,
+                            center[2] + (u[2]*a + v[2]*b)*size] };
+                        let quad = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0),
+                            corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+                        let cap_buffer = glium::VertexBuffer::new(&state.display, &quad).unwrap();
+                        target.clear_stencil(0);
+                        let stencil_params = glium::DrawParameters {
+                            color_mask: (false, false, false, false),
+                            depth: glium::Depth {
+                                test: glium::DepthTest::Overwrite,
+                                write: false,
+                                .. Default::default()
+                            },
+                            stencil: glium::draw_parameters::Stencil {
+                                depth_pass_operation_clockwise: glium::StencilOperation::Invert,
+                                depth_pass_operation_counter_clockwise: glium::StencilOperation::Invert,
+                                .. Default::default()
+                            },
+                            .. params.clone()
+                        };
+                        target.draw(&buffers.vertices, &buffers.indices, &state.program_edge,
+                            &uniforms_hidden, &stencil_params).unwrap();
+                        let cap_params = glium::DrawParameters {
+                            stencil: glium::draw_parameters::Stencil {
+                                test_clockwise: glium::StencilTest::IfNotEqual { mask: 0xff },
+                                test_counter_clockwise: glium::StencilTest::IfNotEqual { mask: 0xff },
+                                reference_value_clockwise: 0,
+                                reference_value_counter_clockwise: 0,
+                                .. Default::default()
+                            },
+                            .. params.clone()
+                        };
+                        let cap_color = if state.render_mode == RenderMode::HiddenLine {
+                            [back.red as f32, back.green as f32, back.blue as f32, back.alpha as f32]
+                        } else {
+                            [object.color[0]*0.6, object.color[1]*0.6, object.color[2]*0.6, 1.0]
+                        };
+                        let uniforms_cap = uniform! {
+                            modelMatrix : translation_scale([0.0, 0.0, 0.0], 1.0),
+                            viewMatrix: vm,
+                            projectionMatrix: pm,
+                            line_color: cap_color,
+                            is_clip: false,
+                        };
+                        target.draw(&cap_buffer, &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                            &state.program_edge, &uniforms_cap, &cap_params).unwrap();
+                    }
+                    if state.render_mode != RenderMode::Shaded {
+                        target.draw(&buffers.vertices, &buffers.indices, &state.program_edge,
+                            &uniforms_edge,&line_params).unwrap();
+                    }
+                    if state.is_problems {
+                        let uniforms_problem = uniform! {
+                            modelMatrix : om,
+                            viewMatrix: vm,
+                            projectionMatrix: pm,
+                            line_color: [1.0f32, 0.0, 1.0, 1.0],
+                            clip_plane: clip_plane,
+                            is_clip: is_clip,
+                        };
+                        if buffers.problem_triangles.len() > 0 {
+                            target.draw(&buffers.problem_triangles,
+                                &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                                &state.program_edge, &uniforms_problem, &problem_params).unwrap();
+                        }
+                        if buffers.problem_edges.len() > 0 {
+                            target.draw(&buffers.problem_edges,
+                                &glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                                &state.program_edge, &uniforms_problem, &problem_params).unwrap();
+                        }
+                    }
+                }
+                let picked = state.picked.and_then(|(id, triangle)| {
+                    model_state.objects.iter().find(|object| object.id == id && object.is_visible)
+                        .map(|object| (object, triangle))
+                });
+                if let Some((object, triangle)) = picked {
+                    let corners: Vec<_> = object.model.indices[3*triangle..3*triangle + 3].iter()
+                        .map(|&i| VertexLight { position: object.model.vertices[i as usize].position }).collect();
+                    let picked_buffer = glium::VertexBuffer::new(&state.display, &corners).unwrap();
+                    let uniforms_picked = uniform! {
+                        modelMatrix : mat4_mul(&sm, &object.model_matrix()),
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                        line_color: [0.0f32, 1.0, 1.0, 1.0],
+                        clip_plane: clip_plane,
+                        is_clip: is_clip,
+                    };
+                    target.draw(&picked_buffer, &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                        &state.program_edge, &uniforms_picked, &problem_params).unwrap();
+                }
+                let mut measure_lines = vec![];
+                let yellow = [1.0, 1.0, 0.0, 1.0];
+                for measurement in &state.measurements {
+                    for segment in measurement.points.windows(2) {
+                        measure_lines.push(VertexGizmo { position: segment[0], color: yellow });
+                        measure_lines.push(VertexGizmo { position: segment[1], color: yellow });
+                    }
+                }
+                for segment in state.measure_points.windows(2) {
+                    measure_lines.push(VertexGizmo { position: segment[0].world, color: yellow });
+                    measure_lines.push(VertexGizmo { position: segment[1].world, color: yellow });
+                }
+                let pending: Vec<_> = state.measure_points.iter().map(|point| point.world).collect();
+                for &p in state.measurements.iter().flat_map(|measurement| measurement.points.iter())
+                        .chain(pending.iter()) {
+                    let size = 0.01 * state.camera.half_height_at(transform_point(&sm, p)) / sm[0][0];
+                    for i in 0..3 {
+                        let (mut a, mut b) = (p, p);
+                        a[i] -= size;
+                        b[i] += size;
+                        measure_lines.push(VertexGizmo { position: a, color: yellow });
+                        measure_lines.push(VertexGizmo { position: b, color: yellow });
+                    }
+                }
+                if !measure_lines.is_empty() {
+                    let measure_buffer = glium::VertexBuffer::new(&state.display, &measure_lines).unwrap();
+                    let uniforms_measure = uniform! {
+                        modelMatrix : sm,
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                    };
+                    let measure_params = glium::DrawParameters {
+                        line_width: Some(2.0),
+                        depth: glium::Depth {
+                            test: glium::DepthTest::Overwrite,
+                            write: false,
+                            .. Default::default()
+                        },
+                        .. params.clone()
+                    };
+                    target.draw(&measure_buffer, &glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                        &state.program_gizmo, &uniforms_measure, &measure_params).unwrap();
+                }
+                if let Some(view) = gizmo_view(state, &model_state, w as f32, h as f32) {
+                    let active = match state.gizmo_drag {
+                        Some(ref drag) => Some(drag.handle),
+                        None => state.gizmo_hover,
+                    };
+                    let mut lines = vec![];
+                    for &(handle, ref points) in &view.handles {
+                        let color = if Some(handle) == active {
+                            [1.0, 1.0, 0.0, 1.0]
+                        } else {
+                            match handle {
+                                GizmoHandle::Axis(i) | GizmoHandle::Plane(i) => AXIS_COLORS[i],
+                                GizmoHandle::Center => [1.0, 1.0, 1.0, 1.0],
+                            }
+                        };
+                        for segment in points.windows(2) {
+                            lines.push(VertexGizmo { position: segment[0], color: color });
+                            lines.push(VertexGizmo { position: segment[1], color: color });
+                        }
+                    }
+                    let gizmo_buffer = glium::VertexBuffer::new(&state.display, &lines).unwrap();
+                    let uniforms_gizmo = uniform! {
+                        modelMatrix : view.scene_matrix,
+                        viewMatrix: vm,
+                        projectionMatrix: pm,
+                    };
+                    let gizmo_params = glium::DrawParameters {
+                        line_width: Some(2.5),
+                        depth: glium::Depth {
+                            test: glium::DepthTest::Overwrite,
+                            write: false,
+                            .. Default::default()
+                        },
+                        .. params.clone()
+                    };
+                    target.draw(&gizmo_buffer, &glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                        &state.program_gizmo, &uniforms_gizmo, &gizmo_params).unwrap();
                 }
-                target.draw(&state.model_buffer, &state.model_indices, &state.program_model,
-                    &uniforms_model,&params).unwrap();
             }
             target.finish().unwrap();
+            let view_projection = mat4_mul(&pm, &mat4_mul(&vm, &sm));
+            let (width, height) = (glarea.get_allocated_width() as f32, glarea.get_allocated_height() as f32);
+            let anchors: Vec<_> = state.measurements.iter().filter_map(|measurement| {
+                let p = measurement.anchor();
+                let m = &view_projection;
+                let w = m[0][3] * p[0] + m[1][3] * p[1] + m[2][3] * p[2] + m[3][3];
+                if w <= 0.0 || !state.is_draw {
+                    return None;
+                }
+                let q = transform_point(m, p);
+                Some((measurement.label(), ((q[0] / w + 1.0) / 2.0 * width) as i32 + 6,
+                      ((1.0 - q[1] / w) / 2.0 * height) as i32 + 6))
+            }).collect();
+            if anchors != state.label_anchors {
+                state.label_anchors = anchors.clone();
+                glib::idle_add(clone!(label_layer, measure_labels; || {
+                    sync_labels(&label_layer, &measure_labels, &anchors);
+                    glib::Continue(false)
+                }));
+            }
             Inhibit(false)
         }));
+    glarea.add_events((gdk::EventMask::BUTTON_PRESS_MASK | gdk::EventMask::BUTTON_RELEASE_MASK |
+        gdk::EventMask::POINTER_MOTION_MASK | gdk::EventMask::SCROLL_MASK).bits() as i32);
+    glarea.connect_button_press_event(clone!(state, model_state; |glarea, event| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let (x, y) = event.get_position();
+        let (w, h) = (glarea.get_allocated_width() as f32, glarea.get_allocated_height() as f32);
+        if event.get_button() == 1 {
+            state.press = Some((x, y));
+            let model_state = model_state.lock().unwrap();
+            if let Some(view) = gizmo_view(state, &model_state, w, h) {
+                if let Some(handle) = pick_gizmo(&view.handles, &view.view_projection, x as f32, y as f32, w, h) {
+                    let object = &model_state.objects[view.object];
+                    let (origin, dir) = scene_ray(state, &view.scene_matrix, x, y, w, h);
+                    let mut drag = GizmoDrag {
+                        handle: handle, mode: state.gizmo_mode, center: view.center, axes: view.axes,
+                        scene_matrix: view.scene_matrix, start: [0.0; 3], start_y: y as f32,
+                        translation: object.translation, rotation: object.rotation, scale: object.scale,
+                    };
+                    if let Some(start) = drag.grab(origin, dir) {
+                        drag.start = start;
+                        state.gizmo_drag = Some(drag);
+                        return Inhibit(false);
+                    }
+                }
+            }
+        }
+        state.drag = Some((event.get_button(), x, y));
+        Inhibit(false)
+    }));
+    glarea.connect_button_release_event(clone!(state, model_state, statusbar, measure_store; |glarea, event| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.drag = None;
+        if state.gizmo_drag.take().is_some() {
+            glarea.queue_render();
+            return Inhibit(false);
+        }
+        let (x, y) = event.get_position();
+        let is_click = match state.press.take() {
+            Some((press_x, press_y)) => event.get_button() == 1 && (x - press_x).abs() < 3.0 && (y - press_y).abs() < 3.0,
+            None => false,
+        };
+        if !is_click {
+            return Inhibit(false);
+        }
+        let (w, h) = (glarea.get_allocated_width() as f32, glarea.get_allocated_height() as f32);
+        let mut model_state = model_state.lock().unwrap();
+        let (origin, dir) = scene_ray(state, &scene_matrix(state, &model_state), x, y, w, h);
+        let context = statusbar.get_context_id("pick");
+        if state.measure_mode != MeasureMode::Off {
+            if let Some((index, _, t)) = model_state.pick(origin, dir) {
+                let object = &model_state.objects[index];
+                let (local_origin, local_dir) = object.ray_to_local(origin, dir);
+                state.measure_points.push(MeasurePoint {
+                    world: [origin[0] + dir[0] * t, origin[1] + dir[1] * t, origin[2] + dir[2] * t],
+                    object: object.id,
+                    local: [local_origin[0] + local_dir[0] * t, local_origin[1] + local_dir[1] * t,
+                            local_origin[2] + local_dir[2] * t],
+                    is_scaled: object.scale != [1.0; 3],
+                });
+                let count = state.measure_mode.point_count();
+                let text = if state.measure_points.len() >= count {
+                    let measurement = Measurement::new(&state.measure_points.drain(..).collect::<Vec<_>>());
+                    let text = measurement.describe();
+                    measure_store.insert_with_values(None, &[0], &[&text]);
+                    state.measurements.push(measurement);
+                    text
+                } else {
+                    format!("point {} of {}, click the next point on the surface", state.measure_points.len(), count)
+                };
+                statusbar.pop(context);
+                statusbar.push(context, &text);
+                glarea.queue_render();
+            }
+            return Inhibit(false);
+        }
+        let text = match model_state.pick(origin, dir) {
+            Some((index, triangle, t)) => {
+                model_state.selected = Some(index);
+                let object = &model_state.objects[index];
+                let corners: Vec<_> = object.model.indices[3*triangle..3*triangle + 3].iter()
+                    .map(|&i| object.model.vertices[i as usize].position).collect();
+                let (a, b, c) = (corners[0], corners[1], corners[2]);
+                let n = object.normal_to_world(normalize3(cross3([b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+                                                                 [c[0] - a[0], c[1] - a[1], c[2] - a[2]])));
+                let p = [origin[0] + dir[0] * t, origin[1] + dir[1] * t, origin[2] + dir[2] * t];
+                state.picked = Some((object.id, triangle));
+                format!("{}: triangle {}, point ({:.3}, {:.3}, {:.3}) mm, normal ({:.3}, {:.3}, {:.3})",
+                        object.name, triangle, p[0], p[1], p[2], n[0], n[1], n[2])
+            },
+            None => {
+                state.picked = None;
+                String::new()
+            },
+        };
+        statusbar.pop(context);
+        statusbar.push(context, &text);
+        glarea.queue_render();
+        Inhibit(false)
+    }));
+    glarea.connect_motion_notify_event(clone!(state, model_state; |glarea, event| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let (w, h) = (glarea.get_allocated_width() as f32, glarea.get_allocated_height() as f32);
+        if let Some(ref drag) = state.gizmo_drag {
+            let (x, y) = event.get_position();
+            let (origin, dir) = scene_ray(state, &drag.scene_matrix, x, y, w, h);
+            let (translation, rotation, scale) = drag.update(origin, dir, y as f32, state.snap);
+            let mut model_state = model_state.lock().unwrap();
+            if let Some(object) = model_state.selected_object_mut() {
+                object.translation = translation;
+                object.rotation = rotation;
+                object.scale = scale;
+            }
+            model_state.transform_revision += 1;
+            glarea.queue_render();
+            return Inhibit(false);
+        }
+        if state.drag.is_none() {
+            let (x, y) = event.get_position();
+            let hover = gizmo_view(state, &model_state.lock().unwrap(), w, h).and_then(|view| {
+                pick_gizmo(&view.handles, &view.view_projection, x as f32, y as f32, w, h)
+            });
+            if hover != state.gizmo_hover {
+                state.gizmo_hover = hover;
+                glarea.queue_render();
+            }
+        }
+        if let Some((button, last_x, last_y)) = state.drag {
+            let (x, y) = event.get_position();
+            let (dx, dy) = ((x - last_x) as f32, (y - last_y) as f32);
+            let is_pan = button == 2 || (button == 1 &&
+                event.get_state().contains(gdk::ModifierType::SHIFT_MASK));
+            if is_pan {
+                state.camera.pan(dx, dy, glarea.get_allocated_height() as f32);
+            } else if button == 1 {
+                state.camera.orbit(dx, dy);
+            }
+            state.drag = Some((button, x, y));
+            glarea.queue_render();
+        }
+        Inhibit(false)
+    }));
+    glarea.connect_scroll_event(clone!(state; |glarea, event| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let factor = match event.get_direction() {
+            gdk::ScrollDirection::Up => 0.9,
+            gdk::ScrollDirection::Down => 1.0 / 0.9,
+            _ => return Inhibit(false),
+        };
+        let (x, y) = event.get_position();
+        state.camera.zoom(factor, x as f32, y as f32,
+                          glarea.get_allocated_width() as f32, glarea.get_allocated_height() as f32);
+        glarea.queue_render();
+        Inhibit(true)
+    }));
     window.set_title("GLArea Example");
     window.set_default_size(1400, 700);
     let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
@@ -432,8 +4195,57 @@ fn main() {
     let model_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
     let lightning_frame = gtk::Frame::new("Lightning");
     let lightning_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    let statistics_frame = gtk::Frame::new("Statistics");
+    let statistics_grid = gtk::Grid::new();
+    statistics_grid.set_row_spacing(3);
+    statistics_grid.set_column_spacing(10);
+    statistics_grid.set_border_width(5);
+    let mut statistics_labels = vec![];
+    for (i, &(name, ref value)) in stats_rows(&MeshStats::default()).iter().enumerate() {
+        let name_label = gtk::Label::new(name);
+        name_label.set_xalign(0.0);
+        let value_label = gtk::Label::new(value.as_str());
+        value_label.set_xalign(1.0);
+        value_label.set_selectable(true);
+        statistics_grid.attach(&name_label, 0, i as i32, 1, 1);
+        statistics_grid.attach(&value_label, 1, i as i32, 1, 1);
+        statistics_labels.push(value_label);
+    }
+    let validation_frame = gtk::Frame::new("Validation");
+    let validation_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    validation_box.set_border_width(5);
+    let validation_label = gtk::Label::new("");
+    validation_label.set_xalign(0.0);
+    validation_box.add(&validation_label);
+    let validation_grid = gtk::Grid::new();
+    validation_grid.set_row_spacing(3);
+    validation_grid.set_column_spacing(10);
+    let mut validation_labels = vec![];
+    for (i, &(name, count)) in MeshReport::default().rows().iter().enumerate() {
+        let name_label = gtk::Label::new(name);
+        name_label.set_xalign(0.0);
+        let count_label = gtk::Label::new(format!("{}", count).as_str());
+        count_label.set_xalign(1.0);
+        validation_grid.attach(&name_label, 0, i as i32, 1, 1);
+        validation_grid.attach(&count_label, 1, i as i32, 1, 1);
+        validation_labels.push(count_label);
+    }
+    validation_box.add(&validation_grid);
+    let problems_button = gtk::CheckButton::new_with_label("highlight problems");
+    problems_button.set_active(true);
+    problems_button.connect_toggled(clone!(state, glarea; |problems_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.is_problems = problems_button.get_active();
+        glarea.queue_render();
+    }));
+    validation_box.add(&problems_button);
+    validation_frame.add(&validation_box);
+    validation_frame.set_border_width(10);
     model_frame.add(&model_box);
     lightning_frame.add(&lightning_box);
+    statistics_frame.add(&statistics_grid);
+    statistics_frame.set_border_width(10);
     let colours_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
     colours_box.set_homogeneous(true);
     colours_box.set_spacing(5);
@@ -489,6 +4301,7 @@ fn main() {
     progress_box.set_vexpand(false);
     progress_box.add(&progress);
     progress_box.set_border_width(5);
+    area_box.add(&statusbar);
     let light_button = gtk::CheckButton::new_with_label("enable");
     light_button.clicked();
     light_button.connect_clicked(clone!(state, glarea; |_light_button| {
@@ -500,12 +4313,6 @@ fn main() {
     lightning_box.add(&light_button);
     let int_button = gtk::SpinButton::new_with_range(0.0, 1.0, 0.05);
     int_button.set_value(1.0);
-    int_button.connect_property_value_notify(clone!(state, glarea; |int_button| {
-        let mut state = state.borrow_mut();
-        let state = state.as_mut().unwrap();
-        state.int = int_button.get_value() as f32;
-        glarea.queue_render();
-    }));
     let int_label = gtk::Label::new("intensivity");
     int_box.add(&int_label);
     int_box.add(&int_button);
@@ -542,6 +4349,213 @@ fn main() {
     let spec_label = gtk::Label::new("specaluraty");
     spec_box.add(&spec_label);
     spec_box.add(&spec_button);
+    let default_light = Light::default();
+    let lights_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let light_combo = gtk::ComboBoxText::new();
+    light_combo.append_text("light 1");
+    light_combo.set_active(0);
+    let add_light_button = gtk::Button::new_with_label("add");
+    let remove_light_button = gtk::Button::new_with_label("remove");
+    lights_box.add(&light_combo);
+    lights_box.add(&add_light_button);
+    lights_box.add(&remove_light_button);
+    lightning_box.add(&lights_box);
+    let light_kind_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let light_kind_combo = gtk::ComboBoxText::new();
+    for name in LightKind::names().iter() {
+        light_kind_combo.append_text(name);
+    }
+    light_kind_combo.set_active(0);
+    let light_enabled_button = gtk::CheckButton::new_with_label("on");
+    light_enabled_button.set_active(default_light.is_enabled);
+    let light_color_button = gtk::ColorButton::new_with_rgba(
+        &gdk::RGBA{red : 1.0, green : 1.0, blue : 1.0, alpha : 1.0});
+    light_color_button.set_title("light colour");
+    light_kind_box.add(&light_kind_combo);
+    light_kind_box.add(&light_enabled_button);
+    light_kind_box.add(&light_color_button);
+    lightning_box.add(&light_kind_box);
+    let light_grid = gtk::Grid::new();
+    light_grid.set_row_spacing(3);
+    light_grid.set_column_spacing(3);
+    let mut light_position_spins = vec![];
+    let mut light_direction_spins = vec![];
+    for (row, &(name, values, range, tooltip)) in [
+            ("position", default_light.position, 10.0, "position relative to the scene center, in scene radii"),
+            ("direction", default_light.direction, 1.0, "direction of directional and spot lights")].iter().enumerate() {
+        light_grid.attach(&gtk::Label::new(name), 0, row as i32, 1, 1);
+        for i in 0..3 {
+            let spin = gtk::SpinButton::new_with_range(-range, range, 0.1);
+            spin.set_digits(2);
+            spin.set_width_chars(4);
+            spin.set_value(values[i] as f64);
+            spin.set_tooltip_text(tooltip);
+            light_grid.attach(&spin, 1 + i as i32, row as i32, 1, 1);
+            if row == 0 {
+                light_position_spins.push(spin);
+            } else {
+                light_direction_spins.push(spin);
+            }
+        }
+    }
+    let attenuation_spin = gtk::SpinButton::new_with_range(0.0, 10.0, 0.1);
+    attenuation_spin.set_digits(2);
+    attenuation_spin.set_value(default_light.attenuation as f64);
+    attenuation_spin.set_tooltip_text("quadratic falloff per scene radius");
+    light_grid.attach(&gtk::Label::new("attenuation"), 0, 2, 1, 1);
+    light_grid.attach(&attenuation_spin, 1, 2, 3, 1);
+    let cutoff_spin = gtk::SpinButton::new_with_range(1.0, 90.0, 1.0);
+    cutoff_spin.set_value(default_light.cutoff as f64);
+    cutoff_spin.set_tooltip_text("spot cone half angle, \u{b0}");
+    light_grid.attach(&gtk::Label::new("cutoff"), 0, 3, 1, 1);
+    light_grid.attach(&cutoff_spin, 1, 3, 3, 1);
+    lightning_box.add(&light_grid);
+    let shadow_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let shadows_button = gtk::CheckButton::new_with_label("shadows");
+    shadows_button.set_tooltip_text("cast shadows from the first enabled light");
+    shadows_button.connect_toggled(clone!(state, glarea; |shadows_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.is_shadows = shadows_button.get_active();
+        glarea.queue_render();
+    }));
+    let ground_button = gtk::CheckButton::new_with_label("ground");
+    ground_button.connect_toggled(clone!(state, glarea; |ground_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.is_ground = ground_button.get_active();
+        glarea.queue_render();
+    }));
+    shadow_box.add(&shadows_button);
+    shadow_box.add(&ground_button);
+    lightning_box.add(&shadow_box);
+    let bias_button = gtk::Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 0.02, 0.0005);
+    bias_button.set_digits(4);
+    bias_button.set_value(0.002);
+    bias_button.connect_value_changed(clone!(state, glarea; |bias_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.shadow_bias = bias_button.get_value() as f32;
+        glarea.queue_render();
+    }));
+    lightning_box.add(&gtk::Label::new("shadow bias"));
+    lightning_box.add(&bias_button);
+    let is_loading_light = Rc::new(std::cell::Cell::new(false));
+    let update_light = clone!(state, glarea, is_loading_light, int_button, light_kind_combo, light_enabled_button,
+                              light_color_button, light_position_spins, light_direction_spins, attenuation_spin,
+                              cutoff_spin; || {
+        if is_loading_light.get() {
+            return;
+        }
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let color = light_color_button.get_rgba();
+        let index = state.light_index;
+        state.lights[index] = Light {
+            kind: LightKind::from_name(&light_kind_combo.get_active_text().unwrap()),
+            color: [color.red as f32, color.green as f32, color.blue as f32],
+            intensity: int_button.get_value() as f32,
+            position: [light_position_spins[0].get_value() as f32, light_position_spins[1].get_value() as f32,
+                       light_position_spins[2].get_value() as f32],
+            direction: [light_direction_spins[0].get_value() as f32, light_direction_spins[1].get_value() as f32,
+                        light_direction_spins[2].get_value() as f32],
+            attenuation: attenuation_spin.get_value() as f32,
+            cutoff: cutoff_spin.get_value() as f32,
+            is_enabled: light_enabled_button.get_active(),
+        };
+        glarea.queue_render();
+    });
+    let update_light = Rc::new(update_light);
+    let load_light = clone!(state, is_loading_light, int_button, light_kind_combo, light_enabled_button,
+                            light_color_button, light_position_spins, light_direction_spins, attenuation_spin,
+                            cutoff_spin; || {
+        let light = {
+            let state = state.borrow();
+            let state = state.as_ref().unwrap();
+            state.lights[state.light_index]
+        };
+        is_loading_light.set(true);
+        light_kind_combo.set_active(light.kind.index());
+        light_enabled_button.set_active(light.is_enabled);
+        light_color_button.set_rgba(&gdk::RGBA{red : light.color[0] as f64, green : light.color[1] as f64,
+                                               blue : light.color[2] as f64, alpha : 1.0});
+        int_button.set_value(light.intensity as f64);
+        for i in 0..3 {
+            light_position_spins[i].set_value(light.position[i] as f64);
+            light_direction_spins[i].set_value(light.direction[i] as f64);
+        }
+        attenuation_spin.set_value(light.attenuation as f64);
+        cutoff_spin.set_value(light.cutoff as f64);
+        is_loading_light.set(false);
+    });
+    let load_light = Rc::new(load_light);
+    int_button.connect_property_value_notify(clone!(update_light; |_int_button| {
+        update_light();
+    }));
+    light_kind_combo.connect_changed(clone!(update_light; |_light_kind_combo| {
+        update_light();
+    }));
+    light_enabled_button.connect_toggled(clone!(update_light; |_light_enabled_button| {
+        update_light();
+    }));
+    light_color_button.connect_color_set(clone!(update_light; |_light_color_button| {
+        update_light();
+    }));
+    for spin in light_position_spins.iter().chain(light_direction_spins.iter())
+            .chain(Some(&attenuation_spin)).chain(Some(&cutoff_spin)) {
+        spin.connect_value_changed(clone!(update_light; |_spin| {
+            update_light();
+        }));
+    }
+    light_combo.connect_changed(clone!(state, load_light; |light_combo| {
+        {
+            let mut state = state.borrow_mut();
+            let state = state.as_mut().unwrap();
+            let index = light_combo.get_active();
+            if index < 0 || index as usize >= state.lights.len() {
+                return;
+            }
+            state.light_index = index as usize;
+        }
+        load_light();
+    }));
+    add_light_button.connect_clicked(clone!(state, glarea, light_combo; |_add_light_button| {
+        let count = {
+            let mut state = state.borrow_mut();
+            let state = state.as_mut().unwrap();
+            if state.lights.len() >= MAX_LIGHTS {
+                return;
+            }
+            let light = state.lights[state.light_index];
+            state.lights.push(Light { position: [-light.position[0], light.position[1], -light.position[2]],
+                                      direction: [-light.direction[0], light.direction[1], -light.direction[2]],
+                                      .. light });
+            state.lights.len()
+        };
+        light_combo.append_text(&format!("light {}", count));
+        light_combo.set_active(count as i32 - 1);
+        glarea.queue_render();
+    }));
+    remove_light_button.connect_clicked(clone!(state, glarea, light_combo; |_remove_light_button| {
+        let count = {
+            let mut state = state.borrow_mut();
+            let state = state.as_mut().unwrap();
+            if state.lights.len() <= 1 {
+                return;
+            }
+            let index = state.light_index;
+            state.lights.remove(index);
+            state.light_index = index.min(state.lights.len() - 1);
+            state.lights.len()
+        };
+        let index = light_combo.get_active().max(0).min(count as i32 - 1);
+        light_combo.remove_all();
+        for i in 0..count {
+            light_combo.append_text(&format!("light {}", i + 1));
+        }
+        light_combo.set_active(index);
+        glarea.queue_render();
+    }));
     let texture_button = gtk::CheckButton::new_with_label("");
     texture_button.clicked();
     texture_button.connect_clicked(clone!(state, glarea; |_texture_button| {
@@ -561,10 +4575,11 @@ fn main() {
     let color_button = gtk::ColorButton::new_with_rgba(
         &gdk::RGBA{red : 1.0, green : 1.0, blue : 1.0, alpha : 1.0});
     color_button.set_title("model`s colour");
-    color_button.connect_color_set(clone!(state, glarea; |color_button| {
-        let mut state = state.borrow_mut();
-        let state = state.as_mut().unwrap();
-        state.model_color = color_button.get_rgba();
+    color_button.connect_color_set(clone!(model_state, glarea; |color_button| {
+        let color = color_button.get_rgba();
+        if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+            object.color = [color.red as f32, color.green as f32, color.blue as f32, color.alpha as f32];
+        }
         glarea.queue_render();
     }));
     let color_label = gtk::Label::new("colour");
@@ -585,129 +4600,941 @@ fn main() {
     b_box.add(&back_button);
     let menu = gtk::Menu::new();
     let open = gtk::MenuItem::new_with_label("Open");
+    let export = gtk::MenuItem::new_with_label("Export As\u{2026}");
     let exit = gtk::MenuItem::new_with_label("Exit");
     let about = gtk::MenuItem::new_with_label("About");
     menu.append(&open);
+    menu.append(&export);
     menu.append(&exit);
     menu.append(&about);
     about.connect_activate(clone!(window; |_about| {
         let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Info,
-                                gtk::ButtonsType::None, "use WASD and 1234");
+                                gtk::ButtonsType::None, "use WASD and RF to move the light, 1234 or left drag to orbit, \
+                                middle drag to pan and the mouse wheel to zoom; numpad 1/3/7 (with Ctrl for the \
+                                opposite side) and 0 select standard views, numpad 5 toggles orthographic, Home fits the model into view; \
+                                with a gizmo chosen, drag its handles to move, rotate or scale the selected object");
         dialog.run();
     }));
     exit.connect_activate(|_exit| {
         gtk::main_quit();
     });
+    export.connect_activate(clone!(window, model_state; |_export| {
+        let export_dialog = gtk::FileChooserDialog::new(Some("export model"),
+                                             Some(&window), gtk::FileChooserAction::Save);
+        export_dialog.add_button("Cancel", gtk::ResponseType::Cancel.into());
+        export_dialog.add_button("Export", gtk::ResponseType::Accept.into());
+        export_dialog.set_do_overwrite_confirmation(true);
+        export_dialog.set_current_name("model.stl");
+        let format_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        let format_combo = gtk::ComboBoxText::new();
+        for name in ExportFormat::names().iter() {
+            format_combo.append_text(name);
+        }
+        format_combo.set_active(0);
+        let units_button = gtk::CheckButton::new_with_label("original units");
+        units_button.set_active(true);
+        format_box.add(&gtk::Label::new("format"));
+        format_box.add(&format_combo);
+        format_box.add(&units_button);
+        let transform_button = gtk::CheckButton::new_with_label("apply transform");
+        transform_button.set_active(true);
+        format_box.add(&transform_button);
+        format_box.show_all();
+        export_dialog.set_extra_widget(&format_box);
+        if export_dialog.run() == gtk::ResponseType::Accept.into() {
+            let format = ExportFormat::from_name(&format_combo.get_active_text().unwrap());
+            let mut path = export_dialog.get_filename().unwrap();
+            if path.extension().is_none() {
+                path.set_extension(format.extension());
+            }
+            let result = match model_state.lock().unwrap().selected_object() {
+                Some(object) => if transform_button.get_active() {
+                    export_model(&path, &object.baked_model(), format, units_button.get_active())
+                } else {
+                    export_model(&path, &object.model, format, units_button.get_active())
+                },
+                None => Err("no object is selected".to_string()),
+            };
+            if let Err(error) = result {
+                let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                        gtk::ButtonsType::Ok, &error);
+                dialog.run();
+                dialog.destroy();
+            }
+        }
+        export_dialog.destroy();
+    }));
     let open_button = gtk::FileChooserButton::new("load model", gtk::FileChooserAction::Open);
     open_button.set_width_chars(19);
     open_button.set_filename(std::path::Path::new("union.stl"));
     let open_dialog_filter = gtk::FileFilter::new();
     open_dialog_filter.add_pattern("*.stl");
-    open_dialog_filter.set_name("*.stl");
+    open_dialog_filter.add_pattern("*.obj");
+    open_dialog_filter.add_pattern("*.ply");
+    open_dialog_filter.set_name("*.stl, *.obj, *.ply");
     open_button.add_filter(&open_dialog_filter);
     open_button.connect_file_set(clone!(model_state, progress; |open_button| {
-        use std::thread;
         progress.set_visible(true);
         let path = open_button.get_filename().unwrap();
-        thread::spawn(clone!(path, model_state; || {
-                model_state.lock().unwrap().is_render = true;
-                let model = make_model(path.to_str().unwrap());
-                if model_state.lock().unwrap().is_render {
-                    model_state.lock().unwrap().model  = model;
-                }
-                model_state.lock().unwrap().is_render = false;
-            }));
+        spawn_model_loader(path, model_state.clone());
     }));
     let open_box = gtk::Box::new(gtk::Orientation::Vertical, 1);
-    let open_label = gtk::Label::new("STL-file");
+    let open_label = gtk::Label::new("STL/OBJ/PLY-file");
     open_box.add(&open_label);
     open_box.add(&open_button);
+    let counts_label = gtk::Label::new("");
+    open_box.add(&counts_label);
+    let size_label = gtk::Label::new("");
+    open_box.add(&size_label);
     model_box.add(&open_box);
     model_box.add(&colours_box);
     let open_texture = gtk::FileChooserButton::new("load texture", gtk::FileChooserAction::Open);
     open_texture.set_width_chars(19);
     open_texture.set_filename(std::path::Path::new("t2.jpg"));
+    let image_patterns = ["*.jpg", "*.jpeg", "*.png", "*.bmp", "*.tga", "*.gif", "*.tif", "*.tiff", "*.ico", "*.ppm"];
     let open_texture_filter = gtk::FileFilter::new();
-    open_texture_filter.add_pattern("*.jpg");
-    open_texture_filter.set_name("*.jpg");
+    for pattern in image_patterns.iter() {
+        open_texture_filter.add_pattern(pattern);
+    }
+    open_texture_filter.set_name("images");
     open_texture.add_filter(&open_texture_filter);
-    open_texture.connect_file_set(clone!(state; |open_texture| {
-        let mut state = state.borrow_mut();
-            let state = state.as_mut().unwrap();
-            let path = open_texture.get_filename().unwrap();
-            let path_str = path.to_str().unwrap();
-            let file = File::open(&path_str).unwrap();
-            use std::io::Read;
-            let mut reader = std::io::BufReader::new(file);
-            let mut buf = Vec::new();
-            let _length = reader.read_to_end(&mut buf);
-            let image = image::load(
-                Cursor::new(&buf.as_slice() as &std::convert::AsRef<[u8]>),image::JPEG).unwrap().to_rgba();
-            let image_dimensions = image.dimensions();
-            let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-            state.texture = glium::texture::Texture2d::new(&state.display, image).unwrap();
+    open_texture.connect_file_set(clone!(state, model_state, glarea, window; |open_texture| {
+        let source = TextureSource::File(open_texture.get_filename().unwrap());
+        let result = ensure_texture(state.borrow_mut().as_mut().unwrap(), &source);
+        if let Err(error) = result {
+            let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(),
+                                    gtk::MessageType::Error, gtk::ButtonsType::Ok, &error);
+            dialog.run();
+            dialog.destroy();
+            return;
+        }
+        if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+            object.texture = Some(source);
+        }
+        glarea.queue_render();
     }));
     let texture_box = gtk::Box::new(gtk::Orientation::Vertical, 1);
-    let texture_label = gtk::Label::new("JPG-file");
+    let texture_label = gtk::Label::new("image file");
     let texture_sub_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
     texture_sub_box.add(&texture_button);
     texture_sub_box.add(&open_texture);
+    let thumbnails: Vec<_> = TEXTURE_LIBRARY.iter().map(|&name| {
+        let thumbnail = image::imageops::resize(&library_image(name), 64, 64, image::FilterType::Triangle);
+        (name, gdk_pixbuf::Pixbuf::new_from_vec(thumbnail.into_raw(), gdk_pixbuf::Colorspace::Rgb,
+                                                true, 8, 64, 64, 64*4))
+    }).collect();
+    let texture_library = library_button(&thumbnails, Rc::new(clone!(state, model_state, glarea, texture_button;
+                                                                      |name| {
+        {
+            let mut state = state.borrow_mut();
+            let state = state.as_mut().unwrap();
+            let source = TextureSource::Library(name);
+            ensure_texture(state, &source).unwrap();
+            if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+                object.texture = Some(source);
+            }
+        }
+        if !texture_button.get_active() {
+            texture_button.set_active(true);
+        }
+        glarea.queue_render();
+    })));
+    texture_sub_box.add(&texture_library);
     texture_box.add(&texture_label);
     texture_box.add(&texture_sub_box);
+    let is_updating = Rc::new(std::cell::Cell::new(false));
+    let mapping_grid = gtk::Grid::new();
+    mapping_grid.set_row_spacing(3);
+    mapping_grid.set_column_spacing(3);
+    let mapping_combo = gtk::ComboBoxText::new();
+    for name in Projection::names().iter() {
+        mapping_combo.append_text(name);
+    }
+    mapping_combo.set_active(Projection::Box.index());
+    mapping_grid.attach(&gtk::Label::new("mapping"), 0, 0, 1, 1);
+    mapping_grid.attach(&mapping_combo, 1, 0, 2, 1);
+    let mut mapping_spins = vec![];
+    for (i, &(name, min, max, step, value)) in [("UV scale", 0.01, 100.0, 0.1, 1.0), ("UV offset", -10.0, 10.0, 0.05, 0.0)]
+            .iter().enumerate() {
+        mapping_grid.attach(&gtk::Label::new(name), 0, 1 + i as i32, 1, 1);
+        for axis in 0..2 {
+            let spin = gtk::SpinButton::new_with_range(min, max, step);
+            spin.set_digits(2);
+            spin.set_width_chars(5);
+            spin.set_value(value);
+            mapping_grid.attach(&spin, 1 + axis, 1 + i as i32, 1, 1);
+            mapping_spins.push(spin);
+        }
+    }
+    let rotation_spin = gtk::SpinButton::new_with_range(-180.0, 180.0, 5.0);
+    rotation_spin.set_tooltip_text("UV rotation, \u{b0}");
+    mapping_grid.attach(&gtk::Label::new("UV rotation"), 0, 3, 1, 1);
+    mapping_grid.attach(&rotation_spin, 1, 3, 2, 1);
+    mapping_spins.push(rotation_spin);
+    texture_box.add(&mapping_grid);
+    let detail_grid = gtk::Grid::new();
+    detail_grid.set_row_spacing(3);
+    detail_grid.set_column_spacing(3);
+    let detail_combo = gtk::ComboBoxText::new();
+    for name in DetailKind::names().iter() {
+        detail_combo.append_text(name);
+    }
+    detail_combo.set_active(0);
+    detail_combo.set_tooltip_text("normal map or grayscale height map");
+    let detail_strength = gtk::SpinButton::new_with_range(0.0, 10.0, 0.1);
+    detail_strength.set_digits(1);
+    detail_strength.set_value(1.0);
+    detail_strength.set_tooltip_text("detail strength");
+    let open_detail = gtk::FileChooserButton::new("load detail map", gtk::FileChooserAction::Open);
+    open_detail.set_width_chars(12);
+    let open_detail_filter = gtk::FileFilter::new();
+    for pattern in image_patterns.iter() {
+        open_detail_filter.add_pattern(pattern);
+    }
+    open_detail_filter.set_name("images");
+    open_detail.add_filter(&open_detail_filter);
+    let set_detail_map = clone!(state, model_state, glarea, window, detail_combo; |source| {
+        let result = ensure_texture(state.borrow_mut().as_mut().unwrap(), &source);
+        if let Err(error) = result {
+            let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(),
+                                    gtk::MessageType::Error, gtk::ButtonsType::Ok, &error);
+            dialog.run();
+            dialog.destroy();
+            return;
+        }
+        if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+            object.detail_map = Some(source);
+        }
+        if detail_combo.get_active() == DetailKind::Off.index() {
+            detail_combo.set_active(DetailKind::BumpMap.index());
+        }
+        glarea.queue_render();
+    });
+    let set_detail_map = Rc::new(set_detail_map);
+    open_detail.connect_file_set(clone!(set_detail_map; |open_detail| {
+        set_detail_map(TextureSource::File(open_detail.get_filename().unwrap()));
+    }));
+    let detail_library = library_button(&thumbnails, Rc::new(clone!(set_detail_map; |name| {
+        set_detail_map(TextureSource::Library(name));
+    })));
+    let update_detail = clone!(model_state, is_updating, glarea, detail_combo, detail_strength; || {
+        if is_updating.get() {
+            return;
+        }
+        if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+            object.detail_kind = DetailKind::from_name(&detail_combo.get_active_text().unwrap());
+            object.detail_strength = detail_strength.get_value() as f32;
+        }
+        glarea.queue_render();
+    });
+    let update_detail = Rc::new(update_detail);
+    detail_combo.connect_changed(clone!(update_detail; |_detail_combo| {
+        update_detail();
+    }));
+    detail_strength.connect_value_changed(clone!(update_detail; |_detail_strength| {
+        update_detail();
+    }));
+    detail_grid.attach(&gtk::Label::new("detail"), 0, 0, 1, 1);
+    detail_grid.attach(&detail_combo, 1, 0, 1, 1);
+    detail_grid.attach(&detail_strength, 2, 0, 1, 1);
+    detail_grid.attach(&open_detail, 0, 1, 2, 1);
+    detail_grid.attach(&detail_library, 2, 1, 1, 1);
+    texture_box.add(&detail_grid);
+    let sampler_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let filter_combo = gtk::ComboBoxText::new();
+    for name in TextureFilter::names().iter() {
+        filter_combo.append_text(name);
+    }
+    filter_combo.set_active(2);
+    filter_combo.set_tooltip_text("texture filtering");
+    filter_combo.connect_changed(clone!(state, glarea; |filter_combo| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.texture_filter = TextureFilter::from_name(&filter_combo.get_active_text().unwrap());
+        glarea.queue_render();
+    }));
+    let wrap_combo = gtk::ComboBoxText::new();
+    for name in TextureWrap::names().iter() {
+        wrap_combo.append_text(name);
+    }
+    wrap_combo.set_active(0);
+    wrap_combo.set_tooltip_text("texture wrap mode");
+    wrap_combo.connect_changed(clone!(state, glarea; |wrap_combo| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.texture_wrap = TextureWrap::from_name(&wrap_combo.get_active_text().unwrap());
+        glarea.queue_render();
+    }));
+    sampler_box.add(&filter_combo);
+    sampler_box.add(&wrap_combo);
+    texture_box.add(&sampler_box);
+    let update_mapping = clone!(model_state, is_updating, glarea, mapping_combo, mapping_spins; || {
+        if is_updating.get() {
+            return;
+        }
+        if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+            object.mapping = TextureMapping {
+                projection: Projection::from_name(&mapping_combo.get_active_text().unwrap()),
+                scale: [mapping_spins[0].get_value() as f32, mapping_spins[1].get_value() as f32],
+                offset: [mapping_spins[2].get_value() as f32, mapping_spins[3].get_value() as f32],
+                rotation: mapping_spins[4].get_value() as f32,
+            };
+        }
+        glarea.queue_render();
+    });
+    let update_mapping = Rc::new(update_mapping);
+    mapping_combo.connect_changed(clone!(update_mapping; |_mapping_combo| {
+        update_mapping();
+    }));
+    for spin in &mapping_spins {
+        spin.connect_value_changed(clone!(update_mapping; |_spin| {
+            update_mapping();
+        }));
+    }
     model_box.add(&texture_box);
+    let shading_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let shading_combo = gtk::ComboBoxText::new();
+    for name in Shading::names().iter() {
+        shading_combo.append_text(name);
+    }
+    shading_combo.set_active(2);
+    let crease_button = gtk::SpinButton::new_with_range(0.0, 180.0, 5.0);
+    crease_button.set_value(30.0);
+    crease_button.set_tooltip_text("crease angle");
+    shading_combo.connect_changed(clone!(model_state, crease_button, progress; |shading_combo| {
+        let shading = Shading::from_name(&shading_combo.get_active_text().unwrap());
+        crease_button.set_sensitive(shading == Shading::AutoSmooth);
+        model_state.lock().unwrap().shading = shading;
+        progress.set_visible(true);
+        spawn_reshade(model_state.clone());
+    }));
+    crease_button.connect_property_value_notify(clone!(model_state, progress; |crease_button| {
+        let is_auto_smooth = {
+            let mut model_state = model_state.lock().unwrap();
+            model_state.crease_angle = crease_button.get_value() as f32;
+            model_state.shading == Shading::AutoSmooth
+        };
+        if is_auto_smooth {
+            progress.set_visible(true);
+            spawn_reshade(model_state.clone());
+        }
+    }));
+    let shading_label = gtk::Label::new("shading");
+    shading_box.add(&shading_combo);
+    shading_box.add(&crease_button);
+    model_box.add(&shading_label);
+    model_box.add(&shading_box);
     open.connect_activate(clone!(window, model_state, progress, open_button; |_open| {
         let open_dialog = gtk::FileChooserDialog::new(Some("load model"),
                                              Some(&window), gtk::FileChooserAction::Open);
         let open_dialog_filter = gtk::FileFilter::new();
         open_dialog_filter.add_pattern("*.stl");
-        open_dialog_filter.set_name("*.stl");
+        open_dialog_filter.add_pattern("*.obj");
+        open_dialog_filter.add_pattern("*.ply");
+        open_dialog_filter.set_name("*.stl, *.obj, *.ply");
         open_dialog.add_filter(&open_dialog_filter);
         open_dialog.connect_file_activated(clone!(model_state, progress, open_button; |open_dialog| {
-        use std::thread;
         progress.set_visible(true);
         let path = open_dialog.get_filename().unwrap();
-        thread::spawn(clone!(path, model_state; || {
-                model_state.lock().unwrap().is_render = true;
-                let model = make_model(path.to_str().unwrap());
-                if model_state.lock().unwrap().is_render {
-                    model_state.lock().unwrap().model  = model;
-                }
-                model_state.lock().unwrap().is_render = false;
-            }));
+        spawn_model_loader(path.clone(), model_state.clone());
         open_button.set_filename(std::path::Path::new(path.to_str().unwrap()));
             open_dialog.destroy();
         }));
         open_dialog.run();
     }));
+    let boolean_frame = gtk::Frame::new("Boolean");
+    boolean_frame.set_border_width(10);
+    let boolean_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    boolean_frame.add(&boolean_box);
+    let boolean_grid = gtk::Grid::new();
+    boolean_grid.set_row_spacing(3);
+    boolean_grid.set_column_spacing(5);
+    let mut boolean_buttons = vec![];
+    for (i, &(name, file)) in [("A", "cube.stl"), ("B", "cube2.stl")].iter().enumerate() {
+        let mesh_button = gtk::FileChooserButton::new("load mesh", gtk::FileChooserAction::Open);
+        mesh_button.set_width_chars(16);
+        mesh_button.set_filename(std::path::Path::new(file));
+        let mesh_filter = gtk::FileFilter::new();
+        mesh_filter.add_pattern("*.stl");
+        mesh_filter.add_pattern("*.obj");
+        mesh_filter.add_pattern("*.ply");
+        mesh_filter.set_name("*.stl, *.obj, *.ply");
+        mesh_button.add_filter(&mesh_filter);
+        boolean_grid.attach(&gtk::Label::new(name), 0, i as i32, 1, 1);
+        boolean_grid.attach(&mesh_button, 1, i as i32, 1, 1);
+        boolean_buttons.push(mesh_button);
+    }
+    boolean_box.add(&boolean_grid);
+    let boolean_combo = gtk::ComboBoxText::new();
+    for name in BoolOp::names().iter() {
+        boolean_combo.append_text(name);
+    }
+    boolean_combo.set_active(0);
+    boolean_box.add(&boolean_combo);
+    let boolean_actions = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    boolean_actions.set_homogeneous(true);
+    let apply_button = gtk::Button::new_with_label("apply");
+    let save_result_button = gtk::Button::new_with_label("save STL\u{2026}");
+    save_result_button.set_sensitive(false);
+    let (mesh_a_button, mesh_b_button) = (boolean_buttons[0].clone(), boolean_buttons[1].clone());
+    apply_button.connect_clicked(clone!(model_state, progress, window, boolean_combo, save_result_button,
+                                        mesh_a_button, mesh_b_button; |_apply_button| {
+        let (path_a, path_b) = match (mesh_a_button.get_filename(), mesh_b_button.get_filename()) {
+            (Some(path_a), Some(path_b)) => (path_a, path_b),
+            _ => {
+                let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                        gtk::ButtonsType::Ok, "choose both meshes A and B first");
+                dialog.run();
+                dialog.destroy();
+                return;
+            },
+        };
+        let op = BoolOp::from_name(&boolean_combo.get_active_text().unwrap());
+        progress.set_visible(true);
+        let stem = |path: &std::path::PathBuf| path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = match op {
+            BoolOp::Union => format!("{} \u{222a} {}", stem(&path_a), stem(&path_b)),
+            BoolOp::Intersection => format!("{} \u{2229} {}", stem(&path_a), stem(&path_b)),
+            BoolOp::DifferenceAB => format!("{} \u{2212} {}", stem(&path_a), stem(&path_b)),
+            BoolOp::DifferenceBA => format!("{} \u{2212} {}", stem(&path_b), stem(&path_a)),
+        };
+        model_state.lock().unwrap().boolean_result = None;
+        spawn_model_job(name, move || boolean_model(path_a.to_str().unwrap(), path_b.to_str().unwrap(), op),
+                        true, model_state.clone());
+        save_result_button.set_sensitive(true);
+    }));
+    save_result_button.connect_clicked(clone!(window, model_state; |_save_result_button| {
+        let save_dialog = gtk::FileChooserDialog::new(Some("save boolean result"),
+                                             Some(&window), gtk::FileChooserAction::Save);
+        save_dialog.add_button("Cancel", gtk::ResponseType::Cancel.into());
+        save_dialog.add_button("Save", gtk::ResponseType::Accept.into());
+        save_dialog.set_do_overwrite_confirmation(true);
+        save_dialog.set_current_name("result.stl");
+        if save_dialog.run() == gtk::ResponseType::Accept.into() {
+            let mut path = save_dialog.get_filename().unwrap();
+            if path.extension().is_none() {
+                path.set_extension("stl");
+            }
+            let result = {
+                let model_state = model_state.lock().unwrap();
+                match model_state.boolean_result {
+                    Some(id) => match model_state.objects.iter().find(|object| object.id == id) {
+                        Some(object) => export_model(&path, &object.baked_model(), ExportFormat::BinaryStl, true),
+                        None => Err("the boolean result has been deleted".to_string()),
+                    },
+                    None => Err("the boolean result is not ready yet".to_string()),
+                }
+            };
+            if let Err(error) = result {
+                let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                        gtk::ButtonsType::Ok, &error);
+                dialog.run();
+                dialog.destroy();
+            }
+        }
+        save_dialog.destroy();
+    }));
+    boolean_actions.add(&apply_button);
+    boolean_actions.add(&save_result_button);
+    boolean_box.add(&boolean_actions);
+    let scene_frame = gtk::Frame::new("Scene");
+    scene_frame.set_border_width(10);
+    let scene_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    scene_frame.add(&scene_box);
+    // the hidden third column is the object id, rows are rebuilt from the model state by the timer
+    let outline_store = gtk::ListStore::new(&[gtk::Type::Bool, gtk::Type::String, gtk::Type::U64]);
+    let outline = gtk::TreeView::new_with_model(&outline_store);
+    outline.set_headers_visible(false);
+    let visible_cell = gtk::CellRendererToggle::new();
+    let visible_column = gtk::TreeViewColumn::new();
+    visible_column.pack_start(&visible_cell, false);
+    visible_column.add_attribute(&visible_cell, "active", 0);
+    outline.append_column(&visible_column);
+    let name_cell = gtk::CellRendererText::new();
+    name_cell.set_property_editable(true);
+    let name_column = gtk::TreeViewColumn::new();
+    name_column.pack_start(&name_cell, true);
+    name_column.add_attribute(&name_cell, "text", 1);
+    outline.append_column(&name_column);
+    visible_cell.connect_toggled(clone!(model_state, outline_store, glarea; |_visible_cell, path| {
+        use glib::ToValue;
+        let iter = match outline_store.get_iter(&path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let id = outline_store.get_value(&iter, 2).get::<u64>();
+        let is_visible = {
+            let mut model_state = model_state.lock().unwrap();
+            match model_state.objects.iter_mut().find(|object| Some(object.id) == id) {
+                Some(object) => {
+                    object.is_visible = !object.is_visible;
+                    object.is_visible
+                },
+                None => return,
+            }
+        };
+        outline_store.set_value(&iter, 0, &is_visible.to_value());
+        glarea.queue_render();
+    }));
+    name_cell.connect_edited(clone!(model_state, outline_store; |_name_cell, path, name| {
+        use glib::ToValue;
+        let iter = match outline_store.get_iter(&path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let id = outline_store.get_value(&iter, 2).get::<u64>();
+        match model_state.lock().unwrap().objects.iter_mut().find(|object| Some(object.id) == id) {
+            Some(object) => object.name = name.to_string(),
+            None => return,
+        }
+        outline_store.set_value(&iter, 1, &name.to_value());
+    }));
+    outline.get_selection().connect_changed(clone!(model_state, is_updating; |selection| {
+        if is_updating.get() {
+            return;
+        }
+        let id = selection.get_selected()
+            .and_then(|(model, iter)| model.get_value(&iter, 2).get::<u64>());
+        let mut model_state = model_state.lock().unwrap();
+        let selected = id.and_then(|id| model_state.objects.iter().position(|object| object.id == id));
+        model_state.selected = selected;
+    }));
+    let outline_scroll = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    outline_scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    outline_scroll.set_min_content_height(100);
+    outline_scroll.set_shadow_type(gtk::ShadowType::In);
+    outline_scroll.add(&outline);
+    scene_box.add(&outline_scroll);
+    let outline_actions = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    outline_actions.set_homogeneous(true);
+    let duplicate_button = gtk::Button::new_with_label("duplicate");
+    duplicate_button.connect_clicked(clone!(model_state; |_duplicate_button| {
+        model_state.lock().unwrap().duplicate_selected();
+    }));
+    let delete_button = gtk::Button::new_with_label("delete");
+    delete_button.connect_clicked(clone!(model_state; |_delete_button| {
+        model_state.lock().unwrap().delete_selected();
+    }));
+    let bake_button = gtk::Button::new_with_label("bake");
+    bake_button.set_tooltip_text("apply the transform to the mesh");
+    bake_button.connect_clicked(clone!(model_state; |_bake_button| {
+        model_state.lock().unwrap().bake_selected();
+    }));
+    outline_actions.add(&duplicate_button);
+    outline_actions.add(&delete_button);
+    outline_actions.add(&bake_button);
+    scene_box.add(&outline_actions);
+    let transform_grid = gtk::Grid::new();
+    transform_grid.set_row_spacing(3);
+    transform_grid.set_column_spacing(3);
+    let mut transform_spins = vec![];
+    let transform_rows = [("move", -10000.0, 10000.0, 1.0, 2), ("rotate", -360.0, 360.0, 5.0, 1),
+                          ("scale", 0.01, 100.0, 0.1, 3)];
+    for (row, &(name, min, max, step, digits)) in transform_rows.iter().enumerate() {
+        transform_grid.attach(&gtk::Label::new(name), 0, row as i32, 1, 1);
+        for axis in 0..3 {
+            let spin = gtk::SpinButton::new_with_range(min, max, step);
+            spin.set_digits(digits);
+            spin.set_width_chars(6);
+            transform_grid.attach(&spin, axis + 1, row as i32, 1, 1);
+            transform_spins.push(spin);
+        }
+    }
+    for (i, spin) in transform_spins.iter().enumerate() {
+        spin.connect_value_changed(clone!(model_state, is_updating, glarea; |spin| {
+            if is_updating.get() {
+                return;
+            }
+            let value = spin.get_value() as f32;
+            if let Some(object) = model_state.lock().unwrap().selected_object_mut() {
+                match i {
+                    0 | 1 | 2 => object.translation[i] = value,
+                    3 | 4 | 5 => object.rotation[i - 3] = value,
+                    _ => object.scale[i - 6] = value,
+                }
+            }
+            glarea.queue_render();
+        }));
+    }
+    scene_box.add(&transform_grid);
+    let gizmo_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let gizmo_combo = gtk::ComboBoxText::new();
+    for name in GizmoMode::names().iter() {
+        gizmo_combo.append_text(name);
+    }
+    gizmo_combo.set_active(0);
+    gizmo_combo.set_tooltip_text("gizmo");
+    gizmo_combo.connect_changed(clone!(state, glarea; |gizmo_combo| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.gizmo_mode = GizmoMode::from_name(&gizmo_combo.get_active_text().unwrap());
+        state.gizmo_hover = None;
+        glarea.queue_render();
+    }));
+    gizmo_box.add(&gizmo_combo);
+    let snap_button = gtk::CheckButton::new_with_label("snap");
+    gizmo_box.add(&snap_button);
+    let mut snap_spins = vec![];
+    for &(value, min, max, step, tooltip) in [(1.0, 0.001, 1000.0, 0.5, "move step, mm"),
+                                              (15.0, 0.1, 180.0, 5.0, "angle step, \u{b0}"),
+                                              (0.1, 0.01, 10.0, 0.05, "scale step")].iter() {
+        let spin = gtk::SpinButton::new_with_range(min, max, step);
+        spin.set_digits(3);
+        spin.set_width_chars(4);
+        spin.set_value(value);
+        spin.set_tooltip_text(tooltip);
+        gizmo_box.add(&spin);
+        snap_spins.push(spin);
+    }
+    let update_snap = clone!(state, snap_button, snap_spins; || {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.snap = if snap_button.get_active() {
+            Some([snap_spins[0].get_value() as f32, snap_spins[1].get_value() as f32,
+                  snap_spins[2].get_value() as f32])
+        } else {
+            None
+        };
+    });
+    let update_snap = Rc::new(update_snap);
+    snap_button.connect_toggled(clone!(update_snap; |_snap_button| {
+        update_snap();
+    }));
+    for spin in &snap_spins {
+        spin.connect_value_changed(clone!(update_snap; |_spin| {
+            update_snap();
+        }));
+    }
+    scene_box.add(&gizmo_box);
+    let measure_frame = gtk::Frame::new("Measure");
+    measure_frame.set_border_width(10);
+    let measure_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    measure_frame.add(&measure_box);
+    let measure_combo = gtk::ComboBoxText::new();
+    for name in MeasureMode::names().iter() {
+        measure_combo.append_text(name);
+    }
+    measure_combo.set_active(0);
+    measure_combo.connect_changed(clone!(state, glarea; |measure_combo| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.measure_mode = MeasureMode::from_name(&measure_combo.get_active_text().unwrap());
+        state.measure_points.clear();
+        glarea.queue_render();
+    }));
+    measure_box.add(&measure_combo);
+    let measure_list = gtk::TreeView::new_with_model(&measure_store);
+    measure_list.set_headers_visible(false);
+    let measure_cell = gtk::CellRendererText::new();
+    let measure_column = gtk::TreeViewColumn::new();
+    measure_column.pack_start(&measure_cell, true);
+    measure_column.add_attribute(&measure_cell, "text", 0);
+    measure_list.append_column(&measure_column);
+    let measure_scroll = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    measure_scroll.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    measure_scroll.set_min_content_height(80);
+    measure_scroll.set_shadow_type(gtk::ShadowType::In);
+    measure_scroll.add(&measure_list);
+    measure_box.add(&measure_scroll);
+    let measure_actions = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    measure_actions.set_homogeneous(true);
+    let copy_button = gtk::Button::new_with_label("copy");
+    copy_button.connect_clicked(clone!(state; |_copy_button| {
+        let state = state.borrow();
+        let state = state.as_ref().unwrap();
+        let text: Vec<_> = state.measurements.iter().map(|measurement| measurement.describe()).collect();
+        gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text.join("\n"));
+    }));
+    let clear_button = gtk::Button::new_with_label("clear");
+    clear_button.connect_clicked(clone!(state, measure_store, glarea; |_clear_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.measurements.clear();
+        state.measure_points.clear();
+        measure_store.clear();
+        glarea.queue_render();
+    }));
+    measure_actions.add(&copy_button);
+    measure_actions.add(&clear_button);
+    measure_box.add(&measure_actions);
+    let section_frame = gtk::Frame::new("Section");
+    section_frame.set_border_width(10);
+    let section_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    section_frame.add(&section_box);
+    let section_options = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let section_button = gtk::CheckButton::new_with_label("enable");
+    let flip_button = gtk::CheckButton::new_with_label("flip");
+    let cap_button = gtk::CheckButton::new_with_label("cap");
+    cap_button.set_active(true);
+    section_options.add(&section_button);
+    section_options.add(&flip_button);
+    section_options.add(&cap_button);
+    section_box.add(&section_options);
+    let section_grid = gtk::Grid::new();
+    section_grid.set_row_spacing(3);
+    section_grid.set_column_spacing(3);
+    let axis_combo = gtk::ComboBoxText::new();
+    for name in ["X", "Y", "Z", "custom"].iter() {
+        axis_combo.append_text(name);
+    }
+    axis_combo.set_active(2);
+    section_grid.attach(&gtk::Label::new("normal"), 0, 0, 1, 1);
+    section_grid.attach(&axis_combo, 1, 0, 3, 1);
+    let mut normal_spins = vec![];
+    for i in 0..3 {
+        let spin = gtk::SpinButton::new_with_range(-1.0, 1.0, 0.05);
+        spin.set_digits(2);
+        spin.set_width_chars(4);
+        spin.set_sensitive(false);
+        section_grid.attach(&spin, 1 + i, 1, 1, 1);
+        normal_spins.push(spin);
+    }
+    normal_spins[2].set_value(1.0);
+    let offset_spin = gtk::SpinButton::new_with_range(-10000.0, 10000.0, 0.1);
+    offset_spin.set_digits(2);
+    offset_spin.set_tooltip_text("plane offset along the normal, mm");
+    section_grid.attach(&gtk::Label::new("offset"), 0, 2, 1, 1);
+    section_grid.attach(&offset_spin, 1, 2, 3, 1);
+    section_box.add(&section_grid);
+    let update_clip = clone!(state, glarea, section_button, flip_button, cap_button, normal_spins, offset_spin; || {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let normal = [normal_spins[0].get_value() as f32, normal_spins[1].get_value() as f32,
+                      normal_spins[2].get_value() as f32];
+        let length = dot3(normal, normal).sqrt();
+        state.clip = if section_button.get_active() && length > 1e-6 {
+            let sign = if flip_button.get_active() { -1.0 } else { 1.0 };
+            let normal = normalize3(normal);
+            Some(([normal[0]*sign, normal[1]*sign, normal[2]*sign], offset_spin.get_value() as f32*sign))
+        } else {
+            None
+        };
+        state.is_cap = cap_button.get_active();
+        glarea.queue_render();
+    });
+    let update_clip = Rc::new(update_clip);
+    for button in [&section_button, &flip_button, &cap_button].iter() {
+        button.connect_toggled(clone!(update_clip; |_button| {
+            update_clip();
+        }));
+    }
+    for spin in normal_spins.iter().chain(Some(&offset_spin)) {
+        spin.connect_value_changed(clone!(update_clip; |_spin| {
+            update_clip();
+        }));
+    }
+    axis_combo.connect_changed(clone!(model_state, normal_spins, offset_spin; |axis_combo| {
+        let axis = axis_combo.get_active();
+        let is_custom = axis < 0 || axis > 2;
+        for spin in &normal_spins {
+            spin.set_sensitive(is_custom);
+        }
+        if is_custom {
+            return;
+        }
+        let (center, _radius) = model_state.lock().unwrap().bounds();
+        for (i, spin) in normal_spins.iter().enumerate() {
+            spin.set_value(if i as i32 == axis { 1.0 } else { 0.0 });
+        }
+        offset_spin.set_value(center[axis as usize] as f64);
+    }));
+    let export_section_button = gtk::Button::new_with_label("export section\u{2026}");
+    export_section_button.connect_clicked(clone!(window, state, model_state; |_export_section_button| {
+        let clip = state.borrow().as_ref().unwrap().clip;
+        let (normal, offset) = match clip {
+            Some(clip) => clip,
+            None => {
+                let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                        gtk::ButtonsType::Ok, "enable the section plane first");
+                dialog.run();
+                dialog.destroy();
+                return;
+            },
+        };
+        let save_dialog = gtk::FileChooserDialog::new(Some("export section"),
+                                             Some(&window), gtk::FileChooserAction::Save);
+        save_dialog.add_button("Cancel", gtk::ResponseType::Cancel.into());
+        save_dialog.add_button("Save", gtk::ResponseType::Accept.into());
+        save_dialog.set_do_overwrite_confirmation(true);
+        save_dialog.set_current_name("section.svg");
+        for &(name, pattern) in [("SVG", "*.svg"), ("DXF", "*.dxf")].iter() {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(name);
+            filter.add_pattern(pattern);
+            save_dialog.add_filter(&filter);
+        }
+        if save_dialog.run() == gtk::ResponseType::Accept.into() {
+            let mut path = save_dialog.get_filename().unwrap();
+            if path.extension().is_none() {
+                let is_dxf = save_dialog.get_filter().and_then(|filter| filter.get_name())
+                    .map(|name| name == "DXF").unwrap_or(false);
+                path.set_extension(if is_dxf { "dxf" } else { "svg" });
+            }
+            let result = export_section(&path, &model_state.lock().unwrap().objects, normal, offset);
+            if let Err(error) = result {
+                let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                        gtk::ButtonsType::Ok, &error);
+                dialog.run();
+                dialog.destroy();
+            }
+        }
+        save_dialog.destroy();
+    }));
+    section_box.add(&export_section_button);
     let menu_bar = gtk::MenuBar::new();
     let file = gtk::MenuItem::new_with_label("File");
     file.set_submenu(Some(&menu));
     menu_bar.append(&file);
-    window.connect_key_press_event(clone!(state, glarea; |_window, key| {
+    let view_frame = gtk::Frame::new("View");
+    view_frame.set_border_width(10);
+    let view_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    view_frame.add(&view_box);
+    let ortho_button = gtk::CheckButton::new_with_label("orthographic");
+    ortho_button.connect_toggled(clone!(state, glarea; |ortho_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        state.camera.is_ortho = ortho_button.get_active();
+        glarea.queue_render();
+    }));
+    view_box.add(&ortho_button);
+    let presets_grid = gtk::Grid::new();
+    presets_grid.set_row_spacing(3);
+    presets_grid.set_column_spacing(3);
+    presets_grid.set_column_homogeneous(true);
+    for (i, &(name, rx, ry)) in VIEW_PRESETS.iter().enumerate() {
+        let preset_button = gtk::Button::new_with_label(name);
+        preset_button.connect_clicked(clone!(state, glarea; |_preset_button| {
+            animate_view(&state, &glarea, rx, ry);
+        }));
+        presets_grid.attach(&preset_button, (i % 2) as i32, (i / 2) as i32, 1, 1);
+    }
+    view_box.add(&presets_grid);
+    let fit_button = gtk::Button::new_with_label("Fit");
+    fit_button.connect_clicked(clone!(model_state, glarea; |_fit_button| {
+        model_state.lock().unwrap().is_fit_pending = true;
+        glarea.queue_render();
+    }));
+    view_box.add(&fit_button);
+    let render_box = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+    let render_combo = gtk::ComboBoxText::new();
+    for name in RenderMode::names().iter() {
+        render_combo.append_text(name);
+    }
+    render_combo.set_active(0);
+    let line_color_button = gtk::ColorButton::new_with_rgba(
+        &gdk::RGBA{red : 1.0, green : 1.0, blue : 1.0, alpha : 1.0});
+    line_color_button.set_title("line colour");
+    line_color_button.set_sensitive(false);
+    let line_width_button = gtk::SpinButton::new_with_range(1.0, 10.0, 0.5);
+    line_width_button.set_value(1.0);
+    line_width_button.set_tooltip_text("line width / point size");
+    line_width_button.set_sensitive(false);
+    render_combo.connect_changed(clone!(state, glarea, line_color_button, line_width_button; |render_combo| {
+        let (line_color, line_width) = {
+            let mut state = state.borrow_mut();
+            let state = state.as_mut().unwrap();
+            state.render_mode = RenderMode::from_name(&render_combo.get_active_text().unwrap());
+            state.line_styles[state.render_mode.index()]
+        };
+        let has_lines = render_combo.get_active_text().unwrap() != RenderMode::names()[0];
+        line_color_button.set_sensitive(has_lines);
+        line_width_button.set_sensitive(has_lines);
+        line_color_button.set_rgba(&line_color);
+        line_width_button.set_value(line_width as f64);
+        glarea.queue_render();
+    }));
+    line_color_button.connect_color_set(clone!(state, glarea; |line_color_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let mode = state.render_mode.index();
+        state.line_styles[mode].0 = line_color_button.get_rgba();
+        glarea.queue_render();
+    }));
+    line_width_button.connect_property_value_notify(clone!(state, glarea; |line_width_button| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().unwrap();
+        let mode = state.render_mode.index();
+        state.line_styles[mode].1 = line_width_button.get_value() as f32;
+        glarea.queue_render();
+    }));
+    render_box.add(&render_combo);
+    render_box.add(&line_color_button);
+    render_box.add(&line_width_button);
+    view_box.add(&gtk::Label::new("render mode"));
+    view_box.add(&render_box);
+    window.connect_key_press_event(clone!(state, glarea, ortho_button, fit_button, light_position_spins; |window, key| {
+        use glib::Cast;
+        // typing into an entry, spin button or the outline name editor goes to that widget
+        if window.get_focus().map(|focus| focus.is::<gtk::Editable>()).unwrap_or(false) {
+            return Inhibit(false);
+        }
         let keyval = gdk::EventKey::get_keyval(&key);
+        let is_ctrl = key.get_state().contains(gdk::ModifierType::CONTROL_MASK);
+        let preset = match keyval {
+            gdk::enums::key::KP_1 => Some(if is_ctrl { 1 } else { 0 }),
+            gdk::enums::key::KP_3 => Some(if is_ctrl { 2 } else { 3 }),
+            gdk::enums::key::KP_7 => Some(if is_ctrl { 5 } else { 4 }),
+            gdk::enums::key::KP_0 => Some(6),
+            gdk::enums::key::Home => {
+                fit_button.clicked();
+                None
+            },
+            gdk::enums::key::KP_5 => {
+                ortho_button.set_active(!ortho_button.get_active());
+                None
+            },
+            _ => None,
+        };
+        if let Some(preset) = preset {
+            let (_, rx, ry) = VIEW_PRESETS[preset];
+            animate_view(&state, &glarea, rx, ry);
+        }
+        let light_step = match keyval {
+            gdk::enums::key::a => Some((0, -0.1)),
+            gdk::enums::key::d => Some((0, 0.1)),
+            gdk::enums::key::s => Some((1, -0.1)),
+            gdk::enums::key::w => Some((1, 0.1)),
+            gdk::enums::key::f => Some((2, -0.1)),
+            gdk::enums::key::r => Some((2, 0.1)),
+            _ => None,
+        };
+        let is_light = state.borrow().as_ref().unwrap().is_light;
+        if let (Some((axis, step)), true) = (light_step, is_light) {
+            let spin = &light_position_spins[axis];
+            spin.set_value(spin.get_value() + step);
+        }
         let mut state = state.borrow_mut();
         let state = state.as_mut().unwrap();
         match keyval {
             gdk::enums::key::Escape => gtk::main_quit(),
-            gdk::enums::key::a => if state.is_light { state.tx -= 0.1 },
-            gdk::enums::key::d => if state.is_light { state.tx += 0.1 },
-            gdk::enums::key::s => if state.is_light { state.ty -= 0.1 },
-            gdk::enums::key::w => if state.is_light { state.ty += 0.1 },
-            gdk::enums::key::f => if state.is_light { state.tz -= 0.1 },
-            gdk::enums::key::r => if state.is_light { state.tz += 0.1 },
-            gdk::enums::key::_4 => state.rx -= 5.0,
-            gdk::enums::key::_3 => state.rx += 5.0,
-            gdk::enums::key::_2 => state.ry += 5.0,
-            gdk::enums::key::_1 => state.ry -= 5.0,
+            gdk::enums::key::_4 => state.camera.rx -= 5.0,
+            gdk::enums::key::_3 => state.camera.rx += 5.0,
+            gdk::enums::key::_2 => state.camera.ry += 5.0,
+            gdk::enums::key::_1 => state.camera.ry -= 5.0,
             _ => (),
         }
         glarea.queue_render();
         Inhibit(false)
     }));
+    button_box.add(&scene_frame);
     button_box.add(&model_frame);
     button_box.add(&lightning_frame);
-    area_sub_box.add(&glarea);
-    hbox.add(&button_box);
+    button_box.add(&statistics_frame);
+    button_box.add(&validation_frame);
+    button_box.add(&boolean_frame);
+    button_box.add(&measure_frame);
+    button_box.add(&section_frame);
+    button_box.add(&view_frame);
+    let glarea_overlay = gtk::Overlay::new();
+    glarea_overlay.add(&glarea);
+    glarea_overlay.add_overlay(&label_layer);
+    glarea_overlay.set_overlay_pass_through(&label_layer, true);
+    area_sub_box.add(&glarea_overlay);
+    let button_scroll = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    button_scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    button_scroll.add(&button_box);
+    hbox.add(&button_scroll);
     scale_box.add(&scale_button);
     hbox.add(&area_box);
     button_box.pack_start(&menu_bar, false, false, 0);
@@ -715,17 +5542,495 @@ fn main() {
     window.show_all();
     glarea.set_visible(true);
     progress.set_visible(false);
-    gtk::timeout_add(1, clone!(glarea; || {
-        glarea.queue_render();
-        return glib::Continue(true);
-    }));
-    gtk::timeout_add(1000, clone!(model_state; || {
-        if !model_state.lock().unwrap().is_render {
-            progress.set_visible(false);
-        } else  {
-            progress.pulse();
+    let mut shown = (None, None, 0);
+    gtk::timeout_add(100, clone!(model_state, window, glarea, counts_label, size_label, color_button; || {
+        let (error, outline_rows, transform) = {
+            let mut model_state = model_state.lock().unwrap();
+            if !model_state.is_render && !model_state.is_reshading {
+                progress.set_visible(false);
+            } else  {
+                progress.pulse();
+            }
+            let mut outline_rows = None;
+            let mut transform = None;
+            if shown.0 != Some(model_state.generation) {
+                outline_rows = Some(model_state.objects.iter()
+                    .map(|object| (object.is_visible, object.name.clone(), object.id)).collect::<Vec<_>>());
+                glarea.queue_render();
+            }
+            if shown != (Some(model_state.generation), model_state.selected, model_state.transform_revision) {
+                shown = (Some(model_state.generation), model_state.selected, model_state.transform_revision);
+                let empty_report = MeshReport::default();
+                let selected = model_state.selected_object();
+                counts_label.set_text(&selected.map(|object| model_counts(&object.shaded)).unwrap_or_default());
+                size_label.set_text(&selected.map(|object| model_size(&object.model)).unwrap_or_default());
+                let stats = selected.map(|object| object.model.stats.clone()).unwrap_or_default();
+                for (label, row) in statistics_labels.iter().zip(stats_rows(&stats).iter()) {
+                    label.set_text(&row.1);
+                }
+                let report = selected.map(|object| &object.model.report).unwrap_or(&empty_report);
+                validation_label.set_text(&selected.map(|_| report.summary()).unwrap_or_default());
+                for (label, row) in validation_labels.iter().zip(report.rows().iter()) {
+                    label.set_text(&format!("{}", row.1));
+                }
+                transform = Some(selected.map(|object| {
+                    let (t, r) = (object.translation, object.rotation);
+                    let s = object.scale;
+                    ([t[0], t[1], t[2], r[0], r[1], r[2], s[0], s[1], s[2]], object.color, object.mapping,
+                     object.detail_kind, object.detail_strength)
+                }));
+            }
+            (model_state.error.take(), outline_rows, transform)
+        };
+        is_updating.set(true);
+        if let Some(outline_rows) = outline_rows {
+            outline_store.clear();
+            for &(is_visible, ref name, id) in &outline_rows {
+                outline_store.insert_with_values(None, &[0, 1, 2], &[&is_visible, name, &id]);
+            }
+        }
+        if let Some(transform) = transform {
+            let selection = outline.get_selection();
+            match shown.1.and_then(|selected| outline_store.iter_nth_child(None, selected as i32)) {
+                Some(iter) => selection.select_iter(&iter),
+                None => selection.unselect_all(),
+            }
+            for spin in &transform_spins {
+                spin.set_sensitive(transform.is_some());
+            }
+            if let Some((values, color, mapping, detail_kind, strength)) = transform {
+                for (spin, value) in transform_spins.iter().zip(values.iter()) {
+                    spin.set_value(*value as f64);
+                }
+                color_button.set_rgba(&gdk::RGBA{red : color[0] as f64, green : color[1] as f64,
+                                                 blue : color[2] as f64, alpha : color[3] as f64});
+                mapping_combo.set_active(mapping.projection.index());
+                let values = [mapping.scale[0], mapping.scale[1], mapping.offset[0], mapping.offset[1], mapping.rotation];
+                for (spin, value) in mapping_spins.iter().zip(values.iter()) {
+                    spin.set_value(*value as f64);
+                }
+                detail_combo.set_active(detail_kind.index());
+                detail_strength.set_value(strength as f64);
+            }
+        }
+        is_updating.set(false);
+        if let Some(error) = error {
+            let dialog = gtk::MessageDialog::new(Some(&window), gtk::DialogFlags::empty(), gtk::MessageType::Error,
+                                    gtk::ButtonsType::Ok, &error);
+            dialog.run();
+            dialog.destroy();
         }
         return glib::Continue(true);
     }));
     gtk::main();
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // outward facing, with a volume of 1/6 and its centroid at (1/4, 1/4, 1/4)
+    const TETRAHEDRON: [[[f32; 3]; 3]; 4] = [
+        [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    ];
+
+    fn ascii_stl(triangles: &[[[f32; 3]; 3]]) -> String {
+        let mut text = "solid test\n".to_string();
+        for triangle in triangles {
+            let n = triangle_normal(triangle);
+            text += &format!("  facet normal {} {} {}\n    outer loop\n", n[0], n[1], n[2]);
+            for p in triangle {
+                text += &format!("      vertex {} {} {}\n", p[0], p[1], p[2]);
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text + "endsolid test\n"
+    }
+
+    fn binary_stl(header: &[u8], triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 80];
+        bytes[..header.len()].copy_from_slice(header);
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            let n = triangle_normal(triangle);
+            for value in n.iter().chain(triangle.iter().flat_map(|p| p.iter())) {
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn stl_kind_is_detected_from_contents() {
+        assert!(is_ascii_stl(ascii_stl(&TETRAHEDRON).as_bytes()));
+        assert!(!is_ascii_stl(&binary_stl(b"", &TETRAHEDRON)));
+    }
+
+    #[test]
+    fn binary_stl_with_solid_header_is_binary() {
+        let mut header = [b' '; 80];
+        header[..5].copy_from_slice(b"solid");
+        let bytes = binary_stl(&header, &TETRAHEDRON);
+        assert!(!is_ascii_stl(&bytes));
+        // a padded or truncated binary file is still not text
+        let mut padded = binary_stl(b"solid part", &TETRAHEDRON);
+        padded.extend_from_slice(&[0; 16]);
+        assert!(!is_ascii_stl(&padded));
+    }
+
+    #[test]
+    fn ascii_stl_is_read_and_checked() {
+        let vertices = read_ascii_stl(&ascii_stl(&TETRAHEDRON)).unwrap();
+        assert_eq!(vertices.len(), 12);
+        for (vertex, p) in vertices.iter().zip(TETRAHEDRON.iter().flat_map(|triangle| triangle.iter())) {
+            assert_eq!(vertex.position, *p);
+        }
+        assert!(read_ascii_stl(&ascii_stl(&TETRAHEDRON).replace("vertex 1 0 0", "vertex 1 NaN 0")).is_err());
+        assert!(read_ascii_stl(&ascii_stl(&TETRAHEDRON).replacen("    endloop\n", "", 1)).is_err());
+    }
+
+    #[test]
+    fn binary_stl_statistics_are_exact() {
+        let (vertices, stats) = read_binary_stl("tetrahedron", &binary_stl(b"", &TETRAHEDRON)).unwrap();
+        assert_eq!(vertices.len(), 12);
+        assert!(stats.is_exact);
+        assert!((stats.volume - 1.0 / 6.0).abs() < 1e-12);
+        for i in 0..3 {
+            assert!((stats.centroid[i] - 0.25).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn statistics_are_exact_in_every_format() {
+        let points: Vec<[f32; 3]> = TETRAHEDRON.iter().flat_map(|triangle| triangle.iter().cloned()).collect();
+        let mut obj = String::new();
+        let mut ply = format!("ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\n\
+                               property float z\nelement face 4\nproperty list uchar int vertex_indices\n\
+                               end_header\n", points.len());
+        for p in &points {
+            obj += &format!("v {} {} {}\n", p[0], p[1], p[2]);
+            ply += &format!("{} {} {}\n", p[0], p[1], p[2]);
+        }
+        for i in 0..4 {
+            obj += &format!("f {} {} {}\n", 3*i + 1, 3*i + 2, 3*i + 3);
+            ply += &format!("3 {} {} {}\n", 3*i, 3*i + 1, 3*i + 2);
+        }
+        let files = vec![("tetrahedron.stl", ascii_stl(&TETRAHEDRON).into_bytes()),
+                         ("tetrahedron.stl", binary_stl(b"", &TETRAHEDRON)),
+                         ("tetrahedron.obj", obj.into_bytes()),
+                         ("tetrahedron.ply", ply.into_bytes())];
+        for (path, bytes) in files {
+            let stats = read_model(path, &bytes).unwrap().stats;
+            assert!(stats.is_exact);
+            assert_eq!(stats.triangle_count, 4);
+            assert!((stats.volume - 1.0 / 6.0).abs() < 1e-12);
+            assert!((stats.area - (1.5 + 3.0f64.sqrt() / 2.0)).abs() < 1e-12);
+            for i in 0..3 {
+                assert!((stats.centroid[i] - 0.25).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn obj_negative_indices_count_back_from_the_end() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf -3/-3 -2/-2 -1/-1\n\
+                    v 0 0 1\nf 1/1 2/2 -1/3 # the new vertex\n";
+        let model = read_obj(text).unwrap();
+        assert!(model.has_tex_coords && !model.has_normals);
+        let positions: Vec<_> = model.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+                                   [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(model.vertices[2].tex_coords, [0.0, 1.0]);
+        assert_eq!(model.vertices[5].tex_coords, [0.0, 1.0]);
+        assert!(read_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n").is_err());
+    }
+
+    #[test]
+    fn obj_polygons_are_fanned() {
+        let model = read_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n").unwrap();
+        assert!(model.has_normals);
+        let positions: Vec<_> = model.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                                   [0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]);
+    }
+
+    fn binary_ply(is_big_endian: bool) -> Vec<u8> {
+        let format = if is_big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!("ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                                 property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+                                 element face 1\nproperty list uchar int vertex_indices\nend_header\n", format)
+            .into_bytes();
+        for (i, p) in [[0.0f32, 0.0, 0.0], [1.5, 0.0, 0.0], [0.0, -2.0, 0.25]].iter().enumerate() {
+            for value in p {
+                let bits = value.to_bits();
+                bytes.extend_from_slice(&if is_big_endian { bits.to_be_bytes() } else { bits.to_le_bytes() });
+            }
+            bytes.extend_from_slice(&[255, 51 * i as u8, 0]);
+        }
+        bytes.push(3);
+        for index in 0..3i32 {
+            bytes.extend_from_slice(&if is_big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn ply_is_read_in_both_byte_orders() {
+        for &is_big_endian in &[false, true] {
+            let model = read_ply(&binary_ply(is_big_endian)).unwrap();
+            assert!(model.has_colors && !model.has_normals && !model.has_tex_coords);
+            let positions: Vec<_> = model.vertices.iter().map(|vertex| vertex.position).collect();
+            assert_eq!(positions, vec![[0.0, 0.0, 0.0], [1.5, 0.0, 0.0], [0.0, -2.0, 0.25]]);
+            assert_eq!(model.vertices[2].color, [1.0, 0.4, 0.0, 1.0]);
+        }
+        let mut truncated = binary_ply(false);
+        truncated.pop();
+        assert!(read_ply(&truncated).is_err());
+    }
+
+    fn vertex(p: [f32; 3]) -> VertexModel {
+        VertexModel { position: p, tex_coords: [p[0], p[1]], normal: [0.0; 3], color: [1.0; 4], tangent: [0.0; 4] }
+    }
+
+    fn cube_soup() -> Vec<VertexModel> {
+        let corner = |i: usize| [(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32];
+        let faces = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let mut vertices = vec![];
+        for face in faces.iter() {
+            for &k in &[0, 1, 2, 0, 2, 3] {
+                vertices.push(vertex(corner(face[k])));
+            }
+        }
+        vertices
+    }
+
+    #[test]
+    fn cube_welds_to_eight_vertices() {
+        let soup = cube_soup();
+        let (vertices, indices) = weld_vertices(&soup, 1e-5, false, false, false);
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(indices.len(), 36);
+        for (&index, original) in indices.iter().zip(soup.iter()) {
+            assert_eq!(vertices[index as usize].position, original.position);
+        }
+        // seams in file UVs are kept apart
+        let mut seam = soup.clone();
+        seam[0].tex_coords = [0.5, 0.5];
+        assert_eq!(weld_vertices(&seam, 1e-5, true, false, false).0.len(), 9);
+    }
+
+    #[test]
+    fn weld_reaches_across_grid_cells() {
+        let soup = [vertex([0.0998, 0.0, 0.0]), vertex([0.1002, 0.0, 0.0]),
+                    vertex([0.2003, 0.0, 0.0]), vertex([0.2007, 0.0, 0.0]), vertex([0.203, 0.0, 0.0])];
+        let (vertices, indices) = weld_vertices(&soup, 1e-3, false, false, false);
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(indices, vec![0, 0, 1, 1, 2]);
+    }
+
+    #[test]
+    fn bvh_hits_match_brute_force() {
+        let random = |i: usize| hash_noise(i as i32, 7) * 10.0 - 5.0;
+        let mut vertices = vec![];
+        for t in 0..300 {
+            let center = [random(12*t), random(12*t + 1), random(12*t + 2)];
+            for k in 0..3 {
+                let j = 12*t + 3 + 3*k;
+                vertices.push(vertex([center[0] + random(j) / 5.0, center[1] + random(j + 1) / 5.0,
+                                      center[2] + random(j + 2) / 5.0]));
+            }
+        }
+        // a broken triangle must not upset the build
+        vertices[0].position = [std::f32::NAN, 0.0, 0.0];
+        let indices: Vec<u32> = (0..vertices.len() as u32).collect();
+        let bvh = Bvh::build(&vertices, &indices);
+        let mut hits = 0;
+        for r in 0..500 {
+            let origin = [random(10000 + 6*r) * 2.0, random(10001 + 6*r) * 2.0, random(10002 + 6*r) * 2.0];
+            let target = [random(10003 + 6*r), random(10004 + 6*r), random(10005 + 6*r)];
+            let dir = normalize3([target[0] - origin[0], target[1] - origin[1], target[2] - origin[2]]);
+            let mut best: Option<(usize, f32)> = None;
+            for t in 0..indices.len() / 3 {
+                let single = Bvh { nodes: vec![BvhNode { min: [std::f32::MIN; 3], max: [std::f32::MAX; 3],
+                                                         start: 0, count: 1, right: 0 }],
+                                   triangles: vec![t as u32] };
+                if let Some((_, distance)) = single.intersect(&vertices, &indices, origin, dir) {
+                    if best.map(|best| distance < best.1).unwrap_or(true) {
+                        best = Some((t, distance));
+                    }
+                }
+            }
+            assert_eq!(bvh.intersect(&vertices, &indices, origin, dir).map(|hit| hit.0), best.map(|hit| hit.0));
+            hits += best.is_some() as usize;
+        }
+        assert!(hits > 50);
+    }
+
+    #[test]
+    fn section_segments_chain_into_a_closed_loop() {
+        let (a, b, c, d) = ([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]);
+        // shuffled, with one segment reversed, plus a loose segment elsewhere
+        let segments = [[c, d], [a, b], [c, b], [d, a], [[5.0, 5.0, 0.0], [6.0, 5.0, 0.0]]];
+        let polylines = chain_segments(&segments, 1e-4);
+        assert_eq!(polylines.len(), 2);
+        let square = &polylines[0];
+        assert_eq!(square.len(), 5);
+        assert_eq!(square.first(), square.last());
+        for corner in &[a, b, c, d] {
+            assert!(square.contains(corner));
+        }
+        assert_eq!(polylines[1], vec![[5.0, 5.0, 0.0], [6.0, 5.0, 0.0]]);
+    }
+
+    #[test]
+    fn measurements_are_labelled_in_model_units() {
+        let point = |world: [f32; 3], object: u64, local: [f32; 3], is_scaled: bool| MeasurePoint {
+            world: world, object: object, local: local, is_scaled: is_scaled,
+        };
+        let distance = Measurement::new(&[point([0.0, 0.0, 0.0], 1, [0.0, 0.0, 0.0], false),
+                                          point([3.0, 4.0, 0.0], 1, [3.0, 4.0, 0.0], false)]);
+        assert_eq!(distance.label(), "5.000");
+        assert!(distance.describe().starts_with("distance 5.000 from (0.000, 0.000, 0.000) to (3.000, 4.000, 0.000)"));
+        let angle = Measurement::new(&[point([1.0, 0.0, 0.0], 1, [1.0, 0.0, 0.0], false),
+                                       point([0.0, 0.0, 0.0], 1, [0.0, 0.0, 0.0], false),
+                                       point([0.0, 2.0, 0.0], 1, [0.0, 2.0, 0.0], false)]);
+        assert_eq!(angle.label(), "90.00\u{b0}");
+        // an object scaled by 2 is measured in its own coordinates
+        let scaled = Measurement::new(&[point([0.0, 0.0, 0.0], 1, [0.0, 0.0, 0.0], true),
+                                        point([6.0, 8.0, 0.0], 1, [3.0, 4.0, 0.0], true)]);
+        assert_eq!(scaled.label(), "5.000");
+        assert_eq!(scaled.points[1], [6.0, 8.0, 0.0]);
+        let across = Measurement::new(&[point([0.0, 0.0, 0.0], 1, [0.0, 0.0, 0.0], true),
+                                        point([6.0, 8.0, 0.0], 2, [6.0, 8.0, 0.0], false)]);
+        assert_eq!(across.label(), "10.000 (scaled)");
+    }
+
+    #[test]
+    fn closed_cube_validates_clean() {
+        let report = validate_mesh(&cube_soup(), 1e-5, false);
+        assert!(report.is_printable());
+        assert!(report.problem_edges.is_empty() && report.problem_triangles.is_empty());
+        // copies of a corner a hair apart on either side of a grid cell boundary still match
+        let mut jittered: Vec<_> = cube_soup().iter()
+            .map(|v| vertex([v.position[0] + 5e-4, v.position[1], v.position[2]])).collect();
+        jittered[0].position[0] += 2e-4;
+        assert!(validate_mesh(&jittered, 1e-3, false).is_printable());
+    }
+
+    #[test]
+    fn cube_defects_are_reported() {
+        let mut open = cube_soup();
+        open.truncate(33);
+        let report = validate_mesh(&open, 1e-5, false);
+        assert_eq!(report.boundary_edges, 3);
+        assert_eq!(report.summary(), "not watertight");
+
+        let mut flipped = cube_soup();
+        flipped.swap(1, 2);
+        let report = validate_mesh(&flipped, 1e-5, false);
+        assert_eq!((report.inconsistent_edges, report.boundary_edges, report.non_manifold_edges), (3, 0, 0));
+        assert_eq!(report.summary(), "closed, but with defects");
+
+        let mut duplicated = cube_soup();
+        let facet: Vec<_> = duplicated[..3].to_vec();
+        duplicated.extend(facet);
+        let report = validate_mesh(&duplicated, 1e-5, false);
+        assert_eq!((report.duplicate_triangles, report.non_manifold_edges), (1, 3));
+
+        let mut degenerate = cube_soup();
+        degenerate.extend(vec![vertex([0.0, 0.0, 0.0]), vertex([0.5, 0.0, 0.0]), vertex([1.0, 0.0, 0.0])]);
+        let report = validate_mesh(&degenerate, 1e-5, false);
+        assert_eq!(report.degenerate_triangles, 1);
+        assert_eq!(report.rows().iter().map(|row| row.1).sum::<usize>(), 1);
+        assert_eq!(report.problem_triangles.len(), 3);
+    }
+
+    #[test]
+    fn cube_normals_split_at_creases() {
+        let (vertices, indices) = weld_vertices(&cube_soup(), 1e-5, false, false, false);
+        let model = ModelData { indices: indices, .. ModelData::new(vertices, false, false, false) };
+        let creased = shade_model(&model, Shading::AutoSmooth, 30.0);
+        assert_eq!(creased.vertices.len(), 24);
+        for triangle in creased.indices.chunks(3) {
+            let p: Vec<_> = triangle.iter().map(|&i| creased.vertices[i as usize].position).collect();
+            let face = normalize3(triangle_normal(&[p[0], p[1], p[2]]));
+            for &i in triangle {
+                assert_eq!(creased.vertices[i as usize].normal, face);
+            }
+        }
+        let smooth = shade_model(&model, Shading::AutoSmooth, 100.0);
+        assert_eq!(smooth.vertices.len(), 8);
+        for vertex in &smooth.vertices {
+            let p = vertex.position;
+            let diagonal = normalize3([p[0] - 0.5, p[1] - 0.5, p[2] - 0.5]);
+            for i in 0..3 {
+                assert!((vertex.normal[i] - diagonal[i]).abs() < 1e-6);
+            }
+        }
+        for vertex in creased.vertices.iter().chain(smooth.vertices.iter()) {
+            let t = [vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]];
+            assert!((dot3(t, t) - 1.0).abs() < 1e-5);
+            assert!(dot3(t, vertex.normal).abs() < 1e-5);
+            assert!(vertex.tangent[3] == 1.0 || vertex.tangent[3] == -1.0);
+        }
+    }
+
+    fn scaled_cube() -> ModelData {
+        let soup: Vec<_> = cube_soup().iter().map(|v| vertex([v.position[0] * 2.0, v.position[1] * 2.0 - 1.0,
+                                                               v.position[2] * 0.5])).collect();
+        let (vertices, indices) = weld_vertices(&soup, 1e-5, false, false, false);
+        ModelData { indices: indices, maximum: 2.0, .. ModelData::new(vertices, false, false, false) }
+    }
+
+    fn exported(model: &ModelData, format: ExportFormat, original_units: bool) -> (Vec<u8>, Vec<[f32; 3]>) {
+        let mut bytes = vec![];
+        write_model(&mut bytes, model, format, original_units).unwrap();
+        let scale = if original_units { 1.0 } else { 0.5 };
+        let expected = model.indices.iter().map(|&i| {
+            let p = model.vertices[i as usize].position;
+            [p[0] * scale, p[1] * scale, p[2] * scale]
+        }).collect();
+        (bytes, expected)
+    }
+
+    fn positions(vertices: &[VertexModel]) -> Vec<[f32; 3]> {
+        vertices.iter().map(|vertex| vertex.position).collect()
+    }
+
+    #[test]
+    fn exports_read_back() {
+        let model = scaled_cube();
+        for &original_units in &[true, false] {
+            let (bytes, expected) = exported(&model, ExportFormat::AsciiStl, original_units);
+            assert_eq!(positions(&read_ascii_stl(&String::from_utf8(bytes).unwrap()).unwrap()), expected);
+            let (bytes, expected) = exported(&model, ExportFormat::Obj, original_units);
+            assert_eq!(positions(&read_obj(&String::from_utf8(bytes).unwrap()).unwrap().vertices), expected);
+            let (bytes, expected) = exported(&model, ExportFormat::Ply, original_units);
+            assert_eq!(positions(&read_ply(&bytes).unwrap().vertices), expected);
+        }
+    }
+
+    #[test]
+    fn binary_stl_export_reads_back() {
+        let model = scaled_cube();
+        for &original_units in &[true, false] {
+            let (bytes, expected) = exported(&model, ExportFormat::BinaryStl, original_units);
+            assert_eq!(bytes.len(), 84 + 50 * 12);
+            // the kernel is free to hand the triangles back in its own order
+            let triangles = |positions: Vec<[f32; 3]>| {
+                let mut triangles: Vec<Vec<[u32; 3]>> = positions.chunks(3).map(|triangle| {
+                    let mut corners: Vec<_> = triangle.iter()
+                        .map(|p| [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]).collect();
+                    corners.sort();
+                    corners
+                }).collect();
+                triangles.sort();
+                triangles
+            };
+            assert_eq!(triangles(positions(&read_binary_stl("cube", &bytes).unwrap().0)), triangles(expected));
+        }
+    }
+}